@@ -1,50 +1,77 @@
+pub mod aes_ni;
+pub mod bitslice;
 pub mod cipher_operations;
 pub mod constants;
+pub mod ctr_drbg;
+pub mod gf256;
 pub mod key;
 pub mod math;
 pub mod modes;
 pub mod padding;
+pub mod rijndael;
 pub mod sbox;
+pub mod sbox_spec;
 pub mod t_tables;
+pub mod trace;
+pub mod trace_export;
 pub mod utils;
+pub mod vpaes;
 use std::ops::{Deref, DerefMut};
 
+// The state is column-major, byte 4c + r is row r of column c. N = 4 * Nb bytes,
+// AES is Nb = 4 and the Rijndael block sizes go up to Nb = 8.
 #[derive(Copy, Clone, Debug)]
-pub struct State {
-    data: [u8; 4 * 4],
+pub struct State<const N: usize = 16> {
+    data: [u8; N],
 }
 
-impl Deref for State {
-    type Target = [u8; 4 * 4];
+impl<const N: usize> Deref for State<N> {
+    type Target = [u8; N];
 
     fn deref(&self) -> &Self::Target {
         &self.data
     }
 }
 
-impl Default for State {
+impl<const N: usize> Default for State<N> {
     fn default() -> Self {
         Self {
-            data: [u8::default(); 4 * 4],
+            data: [u8::default(); N],
         }
     }
 }
 
-impl DerefMut for State {
+impl<const N: usize> DerefMut for State<N> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.data
     }
 }
 
-impl State {
-    pub fn from_hex_vector(message: &[u8; 4 * 4]) -> State {
-        let mut data: [u8; 4 * 4] = [u8::default(); 4 * 4];
-
-        for (i, byte) in message.iter().enumerate() {
-            data[i] = *byte;
-        }
-        State { data }
+impl<const N: usize> State<N> {
+    pub fn from_hex_vector(message: &[u8; N]) -> State<N> {
+        State { data: *message }
+    }
+    pub fn block_words(&self) -> usize {
+        N / 4
+    }
+    pub fn get_row(&self, i: usize) -> [u8; 4] {
+        [
+            self.data[4 * i],
+            self.data[4 * i + 1],
+            self.data[4 * i + 2],
+            self.data[4 * i + 3],
+        ]
     }
+    pub fn get_row_as_word(&self, i: usize) -> u32 {
+        let result: u32 = ((self.data[4 * i] as u32) << 24)
+            ^ ((self.data[4 * i + 1] as u32) << 16)
+            ^ ((self.data[4 * i + 2] as u32) << 8)
+            ^ (self.data[4 * i + 3] as u32);
+        result
+    }
+}
+
+impl State {
     pub fn from_words(words: [[u8; 4]; 4]) -> State {
         let mut data: [u8; 4 * 4] = [u8::default(); 4 * 4];
         let mut iterator = 0;
@@ -60,19 +87,4 @@ impl State {
         let data: [u8; 4 * 4] = [u8::default(); 4 * 4];
         State { data }
     }
-    pub fn get_row(&self, i: usize) -> [u8; 4] {
-        [
-            self.data[4 * i + 0],
-            self.data[4 * i + 1],
-            self.data[4 * i + 2],
-            self.data[4 * i + 3],
-        ]
-    }
-    pub fn get_row_as_word(&self, i: usize) -> u32 {
-        let result: u32 = ((self.data[4 * i + 0] as u32) << 24)
-            ^ ((self.data[4 * i + 1] as u32) << 16)
-            ^ ((self.data[4 * i + 2] as u32) << 8)
-            ^ (self.data[4 * i + 3] as u32);
-        result
-    }
 }
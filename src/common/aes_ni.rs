@@ -0,0 +1,268 @@
+// AES-NI backed round functions. Support is detected once per key expansion, which
+// returns the round keys loaded into registers, and the table based implementation
+// from `cipher_operations` is used when the CPU (or the target architecture) lacks
+// the AES extension.
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{
+    __m128i, _mm_aesdec_si128, _mm_aesdeclast_si128, _mm_aesenc_si128, _mm_aesenclast_si128,
+    _mm_aesimc_si128, _mm_aeskeygenassist_si128, _mm_loadu_si128, _mm_setzero_si128,
+    _mm_storeu_si128, _mm_xor_si128,
+};
+
+#[cfg(test)]
+use std::cell::Cell;
+
+use crate::common::{
+    cipher_operations::{
+        add_round_key, inv_shift_rows, inv_sub_bytes, merged_op, merged_op_inverse, sub_bytes,
+        sub_rows, sub_word,
+    },
+    key::AESKey,
+    State,
+};
+
+pub const PARALLEL_BLOCKS: usize = 8;
+
+#[cfg(test)]
+thread_local! {
+    static HARDWARE_DISABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+// Makes `is_hardware_supported` return false on the current thread, so the fallback
+// can be exercised on a machine with AES-NI.
+#[cfg(test)]
+pub(crate) fn set_hardware_disabled(disabled: bool) {
+    HARDWARE_DISABLED.with(|flag| flag.set(disabled));
+}
+
+pub fn is_hardware_supported() -> bool {
+    #[cfg(test)]
+    if HARDWARE_DISABLED.with(|flag| flag.get()) {
+        return false;
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("aes") && is_x86_feature_detected!("sse2")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn load(bytes: &[u8; 16]) -> __m128i {
+    _mm_loadu_si128(bytes.as_ptr() as *const __m128i)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn store(value: __m128i) -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    _mm_storeu_si128(bytes.as_mut_ptr() as *mut __m128i, value);
+    bytes
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn load_round_key<T>(round: usize, key: &T) -> __m128i
+where
+    T: AESKey,
+{
+    load(&State::from_words(key.get_round_subkey(round)))
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn load_round_keys<T>(key: &T) -> Vec<__m128i>
+where
+    T: AESKey,
+{
+    (0..key.num_rounds() + 1)
+        .map(|round| load_round_key(round, key))
+        .collect()
+}
+
+// aeskeygenassist places SubWord of the highest dword of its input in dword 2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn hardware_sub_word(word: [u8; 4]) -> [u8; 4] {
+    let mut bytes = [0u8; 16];
+    bytes[12..].copy_from_slice(&word);
+    let result = store(_mm_aeskeygenassist_si128(load(&bytes), 0));
+    [result[8], result[9], result[10], result[11]]
+}
+
+#[cfg(target_arch = "x86_64")]
+fn checked_hardware_sub_word(word: [u8; 4]) -> [u8; 4] {
+    if !is_hardware_supported() {
+        return sub_word(word);
+    }
+    unsafe { hardware_sub_word(word) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn hardware_expand_key<T>(key: &mut T, inverse: bool)
+where
+    T: AESKey,
+{
+    key.key_expansion_with(checked_hardware_sub_word);
+
+    if inverse {
+        for round in 1..key.num_rounds() {
+            let mixed =
+                State::from_hex_vector(&store(_mm_aesimc_si128(load_round_key(round, key))));
+            for j in 0..4 {
+                key.set_round_key(4 * round + j, mixed.get_row(j));
+            }
+        }
+    }
+}
+
+// The expanded round keys as loaded by `hardware_key_expansion`, empty when AES-NI
+// is not available. The round functions only use the instructions when they are set.
+#[derive(Clone, Debug, Default)]
+pub struct HardwareRoundKeys {
+    #[cfg(target_arch = "x86_64")]
+    keys: Option<Vec<__m128i>>,
+}
+
+pub fn hardware_key_expansion<T>(key: &mut T, inverse: bool) -> HardwareRoundKeys
+where
+    T: AESKey,
+{
+    #[cfg(target_arch = "x86_64")]
+    if is_hardware_supported() {
+        return unsafe {
+            hardware_expand_key(key, inverse);
+            HardwareRoundKeys {
+                keys: Some(load_round_keys(key)),
+            }
+        };
+    }
+    key.key_expansion(inverse);
+    HardwareRoundKeys::default()
+}
+
+// Each round is issued for up to PARALLEL_BLOCKS independent blocks before moving on,
+// so the aesenc latency of one block is hidden behind the others.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn encrypt_chunk(chunk: &mut [[u8; 16]], round_keys: &[__m128i]) {
+    let mut lanes = [_mm_setzero_si128(); PARALLEL_BLOCKS];
+    let lanes = &mut lanes[..chunk.len()];
+    let last = round_keys.len() - 1;
+
+    for (lane, block) in lanes.iter_mut().zip(chunk.iter()) {
+        *lane = _mm_xor_si128(load(block), round_keys[0]);
+    }
+    for round_key in &round_keys[1..last] {
+        for lane in lanes.iter_mut() {
+            *lane = _mm_aesenc_si128(*lane, *round_key);
+        }
+    }
+    for (lane, block) in lanes.iter().zip(chunk.iter_mut()) {
+        *block = store(_mm_aesenclast_si128(*lane, round_keys[last]));
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn decrypt_chunk(chunk: &mut [[u8; 16]], round_keys: &[__m128i]) {
+    let mut lanes = [_mm_setzero_si128(); PARALLEL_BLOCKS];
+    let lanes = &mut lanes[..chunk.len()];
+    let last = round_keys.len() - 1;
+
+    for (lane, block) in lanes.iter_mut().zip(chunk.iter()) {
+        *lane = _mm_xor_si128(load(block), round_keys[last]);
+    }
+    for round_key in round_keys[1..last].iter().rev() {
+        for lane in lanes.iter_mut() {
+            *lane = _mm_aesdec_si128(*lane, *round_key);
+        }
+    }
+    for (lane, block) in lanes.iter().zip(chunk.iter_mut()) {
+        *block = store(_mm_aesdeclast_si128(*lane, round_keys[0]));
+    }
+}
+
+// The whole block runs in `encrypt_chunk`, so the state stays in a register between
+// rounds. Without AES-NI the rounds use the T-tables.
+pub fn hardware_encrypt_state<T>(mut state: State, key: &T, round_keys: &HardwareRoundKeys) -> State
+where
+    T: AESKey,
+{
+    #[cfg(target_arch = "x86_64")]
+    if let Some(keys) = &round_keys.keys {
+        let mut blocks = [*state];
+        unsafe { encrypt_chunk(&mut blocks, keys) };
+        return State::from_hex_vector(&blocks[0]);
+    }
+    add_round_key(&mut state, key.get_round_subkey(0));
+    for round in 1..key.num_rounds() {
+        state = merged_op(state, round, key);
+    }
+    state = sub_bytes(state);
+    state = sub_rows(state);
+    add_round_key(&mut state, key.get_round_subkey(key.num_rounds()));
+    state
+}
+
+pub fn hardware_decrypt_state<T>(mut state: State, key: &T, round_keys: &HardwareRoundKeys) -> State
+where
+    T: AESKey,
+{
+    #[cfg(target_arch = "x86_64")]
+    if let Some(keys) = &round_keys.keys {
+        let mut blocks = [*state];
+        unsafe { decrypt_chunk(&mut blocks, keys) };
+        return State::from_hex_vector(&blocks[0]);
+    }
+    add_round_key(&mut state, key.get_round_subkey(key.num_rounds()));
+    for round in (1..key.num_rounds()).rev() {
+        state = merged_op_inverse(state, round, key);
+    }
+    state = inv_sub_bytes(state);
+    state = inv_shift_rows(state);
+    add_round_key(&mut state, key.get_round_subkey(0));
+    state
+}
+
+pub fn hardware_encrypt_blocks<T>(blocks: &mut [[u8; 16]], key: &mut T)
+where
+    T: AESKey,
+{
+    let round_keys = hardware_key_expansion(key, false);
+
+    #[cfg(target_arch = "x86_64")]
+    if let Some(keys) = &round_keys.keys {
+        return unsafe {
+            for chunk in blocks.chunks_mut(PARALLEL_BLOCKS) {
+                encrypt_chunk(chunk, keys);
+            }
+        };
+    }
+    for block in blocks.iter_mut() {
+        *block = *hardware_encrypt_state(State::from_hex_vector(block), key, &round_keys);
+    }
+}
+
+pub fn hardware_decrypt_blocks<T>(blocks: &mut [[u8; 16]], key: &mut T)
+where
+    T: AESKey,
+{
+    let round_keys = hardware_key_expansion(key, true);
+
+    #[cfg(target_arch = "x86_64")]
+    if let Some(keys) = &round_keys.keys {
+        return unsafe {
+            for chunk in blocks.chunks_mut(PARALLEL_BLOCKS) {
+                decrypt_chunk(chunk, keys);
+            }
+        };
+    }
+    for block in blocks.iter_mut() {
+        *block = *hardware_decrypt_state(State::from_hex_vector(block), key, &round_keys);
+    }
+}
@@ -0,0 +1,310 @@
+// Table-free AES round functions. The state is transposed into eight 16-bit
+// planes (plane b holds bit b of every state byte) so that SubBytes becomes the
+// Boyar-Peralta boolean circuit and no memory access depends on secret data.
+
+use crate::common::{key::AESKey, State};
+
+type Planes = [u16; 8];
+
+fn pack(bytes: &[u8]) -> Planes {
+    let mut planes = [0u16; 8];
+    for (j, byte) in bytes.iter().enumerate() {
+        for (b, plane) in planes.iter_mut().enumerate() {
+            *plane |= (((*byte >> b) & 1) as u16) << j;
+        }
+    }
+    planes
+}
+
+fn unpack(planes: &Planes, bytes: &mut [u8]) {
+    for (j, byte) in bytes.iter_mut().enumerate() {
+        let mut value = 0;
+        for (b, plane) in planes.iter().enumerate() {
+            value |= (((*plane >> j) & 1) as u8) << b;
+        }
+        *byte = value;
+    }
+}
+
+fn sbox_circuit(planes: &Planes) -> Planes {
+    let u0 = planes[7];
+    let u1 = planes[6];
+    let u2 = planes[5];
+    let u3 = planes[4];
+    let u4 = planes[3];
+    let u5 = planes[2];
+    let u6 = planes[1];
+    let u7 = planes[0];
+
+    let t1 = u0 ^ u3;
+    let t2 = u0 ^ u5;
+    let t3 = u0 ^ u6;
+    let t4 = u3 ^ u5;
+    let t5 = u4 ^ u6;
+    let t6 = t1 ^ t5;
+    let t7 = u1 ^ u2;
+    let t8 = u7 ^ t6;
+    let t9 = u7 ^ t7;
+    let t10 = t6 ^ t7;
+    let t11 = u1 ^ u5;
+    let t12 = u2 ^ u5;
+    let t13 = t3 ^ t4;
+    let t14 = t6 ^ t11;
+    let t15 = t5 ^ t11;
+    let t16 = t5 ^ t12;
+    let t17 = t9 ^ t16;
+    let t18 = u3 ^ u7;
+    let t19 = t7 ^ t18;
+    let t20 = t1 ^ t19;
+    let t21 = u6 ^ u7;
+    let t22 = t7 ^ t21;
+    let t23 = t2 ^ t22;
+    let t24 = t2 ^ t10;
+    let t25 = t20 ^ t17;
+    let t26 = t3 ^ t16;
+    let t27 = t1 ^ t12;
+
+    let m1 = t13 & t6;
+    let m2 = t23 & t8;
+    let m3 = t14 ^ m1;
+    let m4 = t19 & u7;
+    let m5 = m4 ^ m1;
+    let m6 = t3 & t16;
+    let m7 = t22 & t9;
+    let m8 = t26 ^ m6;
+    let m9 = t20 & t17;
+    let m10 = m9 ^ m6;
+    let m11 = t1 & t15;
+    let m12 = t4 & t27;
+    let m13 = m12 ^ m11;
+    let m14 = t2 & t10;
+    let m15 = m14 ^ m11;
+    let m16 = m3 ^ m2;
+    let m17 = m5 ^ t24;
+    let m18 = m8 ^ m7;
+    let m19 = m10 ^ m15;
+    let m20 = m16 ^ m13;
+    let m21 = m17 ^ m15;
+    let m22 = m18 ^ m13;
+    let m23 = m19 ^ t25;
+    let m24 = m22 ^ m23;
+    let m25 = m22 & m20;
+    let m26 = m21 ^ m25;
+    let m27 = m20 ^ m21;
+    let m28 = m23 ^ m25;
+    let m29 = m28 & m27;
+    let m30 = m26 & m24;
+    let m31 = m20 & m23;
+    let m32 = m27 & m31;
+    let m33 = m27 ^ m25;
+    let m34 = m21 & m22;
+    let m35 = m24 & m34;
+    let m36 = m24 ^ m25;
+    let m37 = m21 ^ m29;
+    let m38 = m32 ^ m33;
+    let m39 = m23 ^ m30;
+    let m40 = m35 ^ m36;
+    let m41 = m38 ^ m40;
+    let m42 = m37 ^ m39;
+    let m43 = m37 ^ m38;
+    let m44 = m39 ^ m40;
+    let m45 = m42 ^ m41;
+    let m46 = m44 & t6;
+    let m47 = m40 & t8;
+    let m48 = m39 & u7;
+    let m49 = m43 & t16;
+    let m50 = m38 & t9;
+    let m51 = m37 & t17;
+    let m52 = m42 & t15;
+    let m53 = m45 & t27;
+    let m54 = m41 & t10;
+    let m55 = m44 & t13;
+    let m56 = m40 & t23;
+    let m57 = m39 & t19;
+    let m58 = m43 & t3;
+    let m59 = m38 & t22;
+    let m60 = m37 & t20;
+    let m61 = m42 & t1;
+    let m62 = m45 & t4;
+    let m63 = m41 & t2;
+
+    let l0 = m61 ^ m62;
+    let l1 = m50 ^ m56;
+    let l2 = m46 ^ m48;
+    let l3 = m47 ^ m55;
+    let l4 = m54 ^ m58;
+    let l5 = m49 ^ m61;
+    let l6 = m62 ^ l5;
+    let l7 = m46 ^ l3;
+    let l8 = m51 ^ m59;
+    let l9 = m52 ^ m53;
+    let l10 = m53 ^ l4;
+    let l11 = m60 ^ l2;
+    let l12 = m48 ^ m51;
+    let l13 = m50 ^ l0;
+    let l14 = m52 ^ m61;
+    let l15 = m55 ^ l1;
+    let l16 = m56 ^ l0;
+    let l17 = m57 ^ l1;
+    let l18 = m58 ^ l8;
+    let l19 = m63 ^ l4;
+    let l20 = l0 ^ l1;
+    let l21 = l1 ^ l7;
+    let l22 = l3 ^ l12;
+    let l23 = l18 ^ l2;
+    let l24 = l15 ^ l9;
+    let l25 = l6 ^ l10;
+    let l26 = l7 ^ l9;
+    let l27 = l8 ^ l10;
+    let l28 = l11 ^ l14;
+    let l29 = l11 ^ l17;
+
+    [
+        !(l6 ^ l23),
+        !(l13 ^ l27),
+        l25 ^ l29,
+        l20 ^ l22,
+        l6 ^ l21,
+        !(l19 ^ l28),
+        !(l16 ^ l26),
+        l6 ^ l24,
+    ]
+}
+
+// Inverse of the S-box affine map: x <<< 1 ^ x <<< 3 ^ x <<< 6 ^ 0x05.
+fn inv_affine(planes: &Planes) -> Planes {
+    let mut result = [0u16; 8];
+    for (b, plane) in result.iter_mut().enumerate() {
+        *plane = planes[(b + 7) % 8] ^ planes[(b + 5) % 8] ^ planes[(b + 2) % 8];
+    }
+    result[0] = !result[0];
+    result[2] = !result[2];
+    result
+}
+
+// S(x) = A(x^-1), so A^-1(S(A^-1(x))) = (A^-1(x))^-1 which is the inverse S-box.
+fn inv_sbox_circuit(planes: &Planes) -> Planes {
+    inv_affine(&sbox_circuit(&inv_affine(planes)))
+}
+
+fn shift_rows_plane(plane: u16) -> u16 {
+    let mut result = 0;
+    for c in 0..4 {
+        for r in 0..4 {
+            result |= ((plane >> (4 * ((c + r) % 4) + r)) & 1) << (4 * c + r);
+        }
+    }
+    result
+}
+
+fn inv_shift_rows_plane(plane: u16) -> u16 {
+    let mut result = 0;
+    for c in 0..4 {
+        for r in 0..4 {
+            result |= ((plane >> (4 * c + r)) & 1) << (4 * ((c + r) % 4) + r);
+        }
+    }
+    result
+}
+
+// Moves every byte of a column n rows up, i.e. byte 4c + r takes byte 4c + (r + n) % 4.
+fn rotate_columns(planes: &Planes, n: u32) -> Planes {
+    let low_mask = 0x1111 * (0x0f >> n);
+    let high_mask = 0x1111 * ((0x0f << (4 - n)) & 0x0f);
+    planes.map(|plane| ((plane >> n) & low_mask) | ((plane << (4 - n)) & high_mask))
+}
+
+fn xtime(planes: &Planes) -> Planes {
+    [
+        planes[7],
+        planes[0] ^ planes[7],
+        planes[1],
+        planes[2] ^ planes[7],
+        planes[3] ^ planes[7],
+        planes[4],
+        planes[5],
+        planes[6],
+    ]
+}
+
+fn xor_planes(x: &Planes, y: &Planes) -> Planes {
+    let mut result = *x;
+    for (plane, other) in result.iter_mut().zip(y.iter()) {
+        *plane ^= other;
+    }
+    result
+}
+
+fn mix_columns_planes(planes: &Planes) -> Planes {
+    let r1 = rotate_columns(planes, 1);
+    let r2 = rotate_columns(planes, 2);
+    let r3 = rotate_columns(planes, 3);
+
+    let doubled = xtime(&xor_planes(planes, &r1));
+    xor_planes(&xor_planes(&doubled, &r1), &xor_planes(&r2, &r3))
+}
+
+// InvMixColumns = MixColumns after adding {04}(a0 + a2) to rows 0, 2 and {04}(a1 + a3) to rows 1, 3.
+fn inv_mix_columns_planes(planes: &Planes) -> Planes {
+    let folded = xor_planes(planes, &rotate_columns(planes, 2));
+    let quadrupled = xtime(&xtime(&folded));
+    mix_columns_planes(&xor_planes(planes, &quadrupled))
+}
+
+fn round_key_planes<T>(round: usize, key: &T) -> Planes
+where
+    T: AESKey,
+{
+    pack(&*State::from_words(key.get_round_subkey(round)))
+}
+
+pub fn bitsliced_sub_word(word: [u8; 4]) -> [u8; 4] {
+    let mut result = [0u8; 4];
+    unpack(&sbox_circuit(&pack(&word)), &mut result);
+    result
+}
+
+pub fn bitsliced_sub_bytes(mut state: State) -> State {
+    let planes = sbox_circuit(&pack(&*state));
+    unpack(&planes, &mut *state);
+    state
+}
+
+pub fn bitsliced_inv_sub_bytes(mut state: State) -> State {
+    let planes = inv_sbox_circuit(&pack(&*state));
+    unpack(&planes, &mut *state);
+    state
+}
+
+pub fn bitsliced_key_expansion<T>(key: &mut T)
+where
+    T: AESKey,
+{
+    key.key_expansion_with(bitsliced_sub_word);
+}
+
+pub fn bitsliced_op<T>(mut state: State, i: usize, key: &T) -> State
+where
+    T: AESKey,
+{
+    let mut planes = sbox_circuit(&pack(&*state));
+    planes = planes.map(shift_rows_plane);
+    planes = mix_columns_planes(&planes);
+    planes = xor_planes(&planes, &round_key_planes(i, key));
+
+    unpack(&planes, &mut *state);
+    state
+}
+
+pub fn bitsliced_op_inverse<T>(mut state: State, i: usize, key: &T) -> State
+where
+    T: AESKey,
+{
+    let mut planes = pack(&*state).map(inv_shift_rows_plane);
+    planes = inv_sbox_circuit(&planes);
+    planes = xor_planes(&planes, &round_key_planes(i, key));
+    planes = inv_mix_columns_planes(&planes);
+
+    unpack(&planes, &mut *state);
+    state
+}
@@ -7,46 +7,39 @@ pub fn sub_word(data: [u8; 4]) -> [u8; 4] {
     ];
 }
 
-use crate::common::{math::mul, State};
+use crate::common::{constants::SHIFT_OFFSETS, gf256::Gf256, State};
 
 use super::{
-    constants::{AES_POLYNOMIAL, AES_POLYNOMIAL_DEGREE},
     key::AESKey,
     math::{rot_word, xor_word},
     sbox::*,
     t_tables::*,
 };
-pub fn get_mixed_column(b1: u8, b2: u8, b3: u8, b4: u8, m1: u8, m2: u8, m3: u8, m4: u8) -> [u8; 4] {
+pub fn get_mixed_column(column: [u8; 4], coefficients: [u8; 4]) -> [u8; 4] {
+    let [b1, b2, b3, b4] = column.map(Gf256::from);
+    let [m1, m2, m3, m4] = coefficients.map(Gf256::from);
     [
-        mul(b1, m1) ^ mul(b2, m2) ^ mul(b3, m3) ^ mul(b4, m4),
-        mul(b1, m4) ^ mul(b2, m1) ^ mul(b3, m2) ^ mul(b4, m3),
-        mul(b1, m3) ^ mul(b2, m4) ^ mul(b3, m1) ^ mul(b4, m2),
-        mul(b1, m2) ^ mul(b2, m3) ^ mul(b3, m4) ^ mul(b4, m1),
+        (b1 * m1 + b2 * m2 + b3 * m3 + b4 * m4).into(),
+        (b1 * m4 + b2 * m1 + b3 * m2 + b4 * m3).into(),
+        (b1 * m3 + b2 * m4 + b3 * m1 + b4 * m2).into(),
+        (b1 * m2 + b2 * m3 + b3 * m4 + b4 * m1).into(),
     ]
 }
-pub fn get_mixed_columns(state: &mut State, m1: u8, m2: u8, m3: u8, m4: u8) -> [[u8; 4]; 4] {
-    [
-        get_mixed_column(state[0], state[1], state[2], state[3], m1, m2, m3, m4),
-        get_mixed_column(state[4], state[5], state[6], state[7], m1, m2, m3, m4),
-        get_mixed_column(state[8], state[9], state[10], state[11], m1, m2, m3, m4),
-        get_mixed_column(state[12], state[13], state[14], state[15], m1, m2, m3, m4),
-    ]
-}
-pub fn inv_mix_columns(state: &mut State) {
-    let mixed_columns = get_mixed_columns(state, 0x0e, 0x0b, 0x0d, 0x09);
-
-    for i in 0..16 {
-        state[i] = mixed_columns[i / 4][i % 4];
+pub fn get_mixed_columns<const N: usize>(state: &mut State<N>, m1: u8, m2: u8, m3: u8, m4: u8) {
+    for column in state.chunks_exact_mut(4) {
+        let mixed = get_mixed_column(
+            [column[0], column[1], column[2], column[3]],
+            [m1, m2, m3, m4],
+        );
+        column.copy_from_slice(&mixed);
     }
 }
+pub fn inv_mix_columns<const N: usize>(state: &mut State<N>) {
+    get_mixed_columns(state, 0x0e, 0x0b, 0x0d, 0x09);
+}
 
-pub fn mix_columns(mut state: State) -> State {
-    let mixed_columns = get_mixed_columns(&mut state, 0x02, 0x03, 1, 1);
-
-    for i in 0..16 {
-        state[i] = mixed_columns[i / 4][i % 4];
-    }
-
+pub fn mix_columns<const N: usize>(mut state: State<N>) -> State<N> {
+    get_mixed_columns(&mut state, 0x02, 0x03, 1, 1);
     state
 }
 pub fn get_sbox_index(byte: u8) -> (u8, u8) {
@@ -61,82 +54,66 @@ pub fn inv_sub_byte(byte: u8) -> u8 {
     INVERSE_SBOX_TABLE[x as usize][y as usize]
 }
 
-pub fn sub_bytes(mut state: State) -> State {
+pub fn sub_bytes<const N: usize>(mut state: State<N>) -> State<N> {
     for byte in state.iter_mut() {
         *byte = sub_byte(*byte);
     }
     state
 }
 
-pub fn byte_degree(mut b: u16) -> usize {
-    let mut ctr = 14;
-
-    while ctr > 0 {
-        if b >= 0x8000 {
-            return ctr + 1;
-        }
-        b <<= 1;
-        ctr -= 1;
-    }
-
-    return ctr + 1;
+pub const fn mult(a: u8, b: u8) -> u8 {
+    Gf256::new(a).multiply(Gf256::new(b)).value()
 }
-
-pub fn reduce_poly(mut polynomial: u16) -> u8 {
-    let mut polynomial_degree = byte_degree(polynomial);
-
-    while polynomial_degree >= AES_POLYNOMIAL_DEGREE {
-        (polynomial, polynomial_degree) = reduce_and_get_degree(polynomial);
+// Shift-and-add multiplication in GF(2^degree) = GF(2)[x] / (modulus) for degree <= 8,
+// used for other reduction polynomials and the small-scale variants in GF(2^4). Like
+// `Gf256::multiply` it always runs `modulus_degree` steps and reduces with a mask.
+pub fn mult_with(a: u8, b: u8, modulus: u16, modulus_degree: usize) -> u8 {
+    let mut result: u16 = 0;
+    let mut a = a as u16;
+    let mut b = b as u16;
+    for _ in 0..modulus_degree {
+        result ^= a & 0u16.wrapping_sub(b & 1);
+        a <<= 1;
+        a ^= modulus & 0u16.wrapping_sub((a >> modulus_degree) & 1);
+        b >>= 1;
     }
-
-    polynomial as u8
-}
-fn reduce_and_get_degree(polynomial: u16) -> (u16, usize) {
-    let polynomial_degree = byte_degree(polynomial);
-    let reduced_polynomial =
-        polynomial ^ (AES_POLYNOMIAL << (polynomial_degree - AES_POLYNOMIAL_DEGREE));
-    (reduced_polynomial, byte_degree(reduced_polynomial))
-}
-fn multiply_byte(a: u8, b: u8, i: usize, j: usize) -> u16 {
-    if ((0x80 >> i) & a) != 0 && ((0x80 >> j) & b) != 0 {
-        return 0x8000 >> (i + j) + 1;
-    }
-    0
-}
-pub fn mult(a: u8, b: u8) -> u8 {
-    let mut prod_byte: u16 = 0;
-    for i in 0..8 {
-        for j in 0..8 {
-            prod_byte ^= multiply_byte(a, b, i, j);
-        }
-    }
-    reduce_poly(prod_byte)
+    result as u8
 }
 
-pub fn inverse(byte: u8) -> u8 {
-    if byte == 0 {
-        return 0;
+pub const fn inverse(byte: u8) -> u8 {
+    Gf256::new(byte).inv().value()
+}
+// a^(2^degree - 2) = a^-1 in GF(2^degree), 0 is mapped to 0. Every bit of the
+// exponent costs a square and a multiplication, the product is kept with a mask.
+pub fn inverse_with(byte: u8, modulus: u16, modulus_degree: usize) -> u8 {
+    let mut result = 1;
+    let mut square = byte;
+    let exponent = (1usize << modulus_degree) - 2;
+    for bit in 0..modulus_degree {
+        let product = mult_with(result, square, modulus, modulus_degree);
+        let keep = 0u8.wrapping_sub(((exponent >> bit) & 1) as u8);
+        result = (product & keep) | (result & !keep);
+        square = mult_with(square, square, modulus, modulus_degree);
     }
-    for i in 0..0xFF {
-        let mult_res: u8 = mult(byte, i + 1);
-        if mult_res == 1 {
-            return i + 1;
-        }
+    // Only fails for a reducible modulus, which is public.
+    let non_zero = (byte | byte.wrapping_neg()) >> 7;
+    if non_zero & (mult_with(byte, result, modulus, modulus_degree) != 1) as u8 != 0 {
+        panic!(
+            "{:#x} has no inverse modulo {:#x}, the modulus is not irreducible",
+            byte, modulus
+        );
     }
-    0x1c
+    result
 }
-pub fn sbox_no_mem(byte: u8) -> u8 {
+// S(x) = A(x^-1), the affine map of FIPS PUB 197 section 5.1.1. These are const so
+// the S-box and T-tables are generated from them at compile time.
+pub const fn sbox_no_mem(byte: u8) -> u8 {
     let q = inverse(byte);
 
-    return q
-        ^ ((q << 1) | (q >> 7))
-        ^ ((q << 2) | (q >> 6))
-        ^ ((q << 3) | (q >> 5))
-        ^ ((q << 4) | (q >> 4))
-        ^ 0x63;
+    q ^ q.rotate_left(1) ^ q.rotate_left(2) ^ q.rotate_left(3) ^ q.rotate_left(4) ^ 0x63
 }
-pub fn inv_sub_byte_no_mem(q: u8) -> u8 {
-    let q = ((q << 1) | (q >> 7)) ^ ((q << 3) | (q >> 5)) ^ ((q << 6) | (q >> 2)) ^ 0x05;
+pub const fn inv_sub_byte_no_mem(q: u8) -> u8 {
+    let q = q.rotate_left(1) ^ q.rotate_left(3) ^ q.rotate_left(6) ^ 0x05;
 
     inverse(q)
 }
@@ -152,111 +129,121 @@ pub fn inv_sub_bytes_no_mem(mut state: State) -> State {
     }
     state
 }
-pub fn inv_sub_bytes(mut state: State) -> State {
+pub fn inv_sub_bytes<const N: usize>(mut state: State<N>) -> State<N> {
     for byte in state.iter_mut() {
         *byte = inv_sub_byte(*byte);
     }
     state
 }
 
-pub fn sub_rows(mut state: State) -> State {
-    let temp = state[1];
-
-    state[1] = state[5];
-    state[5] = state[9];
-    state[9] = state[13];
-    state[13] = temp;
-
-    state.swap(2, 10);
-    state.swap(6, 14);
-
-    let temp = state[15];
-
-    state[15] = state[11];
-    state[11] = state[7];
-    state[7] = state[3];
-    state[3] = temp;
-    state
+// Row r is rotated left by SHIFT_OFFSETS[Nb - 4][r] columns, r columns for AES.
+fn shift_rows_by_offsets<const N: usize>(state: State<N>, inverse: bool) -> State<N> {
+    let nb = state.block_words();
+    let mut shifted = state;
+    for (r, offset) in SHIFT_OFFSETS[nb - 4].iter().enumerate() {
+        for c in 0..nb {
+            let source = if inverse {
+                (c + nb - offset) % nb
+            } else {
+                (c + offset) % nb
+            };
+            shifted[4 * c + r] = state[4 * source + r];
+        }
+    }
+    shifted
 }
 
-pub fn inv_shift_rows(mut state: State) -> State {
-    let temp = state[13];
-    state[13] = state[9];
-    state[9] = state[5];
-    state[5] = state[1];
-    state[1] = temp;
-
-    state.swap(2, 10);
-    state.swap(6, 14);
-
-    let temp = state[3];
-    state[3] = state[7];
-    state[7] = state[11];
-    state[11] = state[15];
-    state[15] = temp;
+pub fn sub_rows<const N: usize>(state: State<N>) -> State<N> {
+    shift_rows_by_offsets(state, false)
+}
 
-    state
+pub fn inv_shift_rows<const N: usize>(state: State<N>) -> State<N> {
+    shift_rows_by_offsets(state, true)
 }
-pub fn add_round_key(state: &mut State, round_key: [[u8; 4]; 4]) {
+// The round key has one word per column of the state.
+pub fn add_round_key<const N: usize>(state: &mut State<N>, round_key: impl AsRef<[[u8; 4]]>) {
     for (i, byte) in state.iter_mut().enumerate() {
-        *byte ^= round_key[i / 4][i % 4];
+        *byte ^= round_key.as_ref()[i / 4][i % 4];
     }
 }
 
-pub fn merged_op_inverse<T>(mut state: State, i: usize, key: &T) -> State
+pub fn merged_op_inverse<T>(state: State, i: usize, key: &T) -> State
 where
     T: AESKey,
 {
-    let mut w1 = xor_word(TD0[state[0] as usize], TD1[state[13] as usize]);
-    w1 = xor_word(w1, TD2[state[10] as usize]);
-    w1 = xor_word(w1, TD3[state[7] as usize]);
+    merged_op_inverse_with(state, i, key, [&TD0, &TD1, &TD2, &TD3])
+}
+// The TD0..TD3 tables are passed in, so tables generated for another S-box work too.
+pub fn merged_op_inverse_with<T>(
+    mut state: State,
+    i: usize,
+    key: &T,
+    [td0, td1, td2, td3]: [&[[u8; 4]; 256]; 4],
+) -> State
+where
+    T: AESKey,
+{
+    let mut w1 = xor_word(td0[state[0] as usize], td1[state[13] as usize]);
+    w1 = xor_word(w1, td2[state[10] as usize]);
+    w1 = xor_word(w1, td3[state[7] as usize]);
 
-    let mut w2 = xor_word(TD0[state[4] as usize], TD1[state[1] as usize]);
-    w2 = xor_word(w2, TD2[state[14] as usize]);
-    w2 = xor_word(w2, TD3[state[11] as usize]);
+    let mut w2 = xor_word(td0[state[4] as usize], td1[state[1] as usize]);
+    w2 = xor_word(w2, td2[state[14] as usize]);
+    w2 = xor_word(w2, td3[state[11] as usize]);
 
-    let mut w3 = xor_word(TD0[state[8] as usize], TD1[state[5] as usize]);
-    w3 = xor_word(w3, TD2[state[2] as usize]);
-    w3 = xor_word(w3, TD3[state[15] as usize]);
+    let mut w3 = xor_word(td0[state[8] as usize], td1[state[5] as usize]);
+    w3 = xor_word(w3, td2[state[2] as usize]);
+    w3 = xor_word(w3, td3[state[15] as usize]);
 
-    let mut w4 = xor_word(TD0[state[12] as usize], TD1[state[9] as usize]);
-    w4 = xor_word(w4, TD2[state[6] as usize]);
-    w4 = xor_word(w4, TD3[state[3] as usize]);
+    let mut w4 = xor_word(td0[state[12] as usize], td1[state[9] as usize]);
+    w4 = xor_word(w4, td2[state[6] as usize]);
+    w4 = xor_word(w4, td3[state[3] as usize]);
 
     let w = [w1, w2, w3, w4];
 
     for j in 0..4 {
-        state[4 * j + 0] = w[j][0] ^ key.get_round_key(i * 4 + j)[0];
+        state[4 * j] = w[j][0] ^ key.get_round_key(i * 4 + j)[0];
         state[4 * j + 1] = w[j][1] ^ key.get_round_key(i * 4 + j)[1];
         state[4 * j + 2] = w[j][2] ^ key.get_round_key(i * 4 + j)[2];
         state[4 * j + 3] = w[j][3] ^ key.get_round_key(i * 4 + j)[3];
     }
     state
 }
-pub fn merged_op<T>(mut state: State, i: usize, key: &T) -> State
+pub fn merged_op<T>(state: State, i: usize, key: &T) -> State
+where
+    T: AESKey,
+{
+    merged_op_with(state, i, key, [&T0, &T1, &T2, &T3])
+}
+pub fn merged_op_with<T>(
+    mut state: State,
+    i: usize,
+    key: &T,
+    [t0, t1, t2, t3]: [&[[u8; 4]; 256]; 4],
+) -> State
 where
     T: AESKey,
 {
-    let mut w1 = xor_word(T0[state[0] as usize], T1[state[5] as usize]);
-    w1 = xor_word(w1, T2[state[10] as usize]);
-    w1 = xor_word(w1, T3[state[15] as usize]);
+    let mut w1 = xor_word(t0[state[0] as usize], t1[state[5] as usize]);
+    w1 = xor_word(w1, t2[state[10] as usize]);
+    w1 = xor_word(w1, t3[state[15] as usize]);
 
-    let mut w2 = xor_word(T0[state[4] as usize], T1[state[9] as usize]);
-    w2 = xor_word(w2, T2[state[14] as usize]);
-    w2 = xor_word(w2, T3[state[3] as usize]);
+    let mut w2 = xor_word(t0[state[4] as usize], t1[state[9] as usize]);
+    w2 = xor_word(w2, t2[state[14] as usize]);
+    w2 = xor_word(w2, t3[state[3] as usize]);
 
-    let mut w3 = xor_word(T0[state[8] as usize], T1[state[13] as usize]);
-    w3 = xor_word(w3, T2[state[2] as usize]);
-    w3 = xor_word(w3, T3[state[7] as usize]);
+    let mut w3 = xor_word(t0[state[8] as usize], t1[state[13] as usize]);
+    w3 = xor_word(w3, t2[state[2] as usize]);
+    w3 = xor_word(w3, t3[state[7] as usize]);
 
-    let mut w4 = xor_word(T0[state[12] as usize], T1[state[1] as usize]);
-    w4 = xor_word(w4, T2[state[6] as usize]);
-    w4 = xor_word(w4, T3[state[11] as usize]);
+    let mut w4 = xor_word(t0[state[12] as usize], t1[state[1] as usize]);
+    w4 = xor_word(w4, t2[state[6] as usize]);
+    w4 = xor_word(w4, t3[state[11] as usize]);
 
     let w = [w1, w2, w3, w4];
 
     for j in 0..4 {
-        state[4 * j + 0] = w[j][0] ^ key.get_round_key(i * 4 + j)[0];
+        state[4 * j] = w[j][0] ^ key.get_round_key(i * 4 + j)[0];
         state[4 * j + 1] = w[j][1] ^ key.get_round_key(i * 4 + j)[1];
         state[4 * j + 2] = w[j][2] ^ key.get_round_key(i * 4 + j)[2];
         state[4 * j + 3] = w[j][3] ^ key.get_round_key(i * 4 + j)[3];
@@ -265,14 +252,81 @@ where
     state
 }
 
-pub fn mult_one(b: u8) -> u8 {
-    let high_bit = b & 0x80;
-    let mut temp = b;
-    temp = temp << 1;
-    if high_bit != 0 {
-        temp ^= 0x1b;
+// T1[x], T2[x] and T3[x] are T0[x] rotated right by one, two and three bytes.
+fn rotate_table_word(word: [u8; 4], n: usize) -> [u8; 4] {
+    [
+        word[(4 - n) % 4],
+        word[(5 - n) % 4],
+        word[(6 - n) % 4],
+        word[(7 - n) % 4],
+    ]
+}
+
+fn compact_column(table: &[[u8; 4]; 256], b0: u8, b1: u8, b2: u8, b3: u8) -> [u8; 4] {
+    let mut w = table[b0 as usize];
+    w = xor_word(w, rotate_table_word(table[b1 as usize], 1));
+    w = xor_word(w, rotate_table_word(table[b2 as usize], 2));
+    xor_word(w, rotate_table_word(table[b3 as usize], 3))
+}
+
+pub fn compact_merged_op_inverse<T>(mut state: State, i: usize, key: &T) -> State
+where
+    T: AESKey,
+{
+    let w = [
+        compact_column(&TD0, state[0], state[13], state[10], state[7]),
+        compact_column(&TD0, state[4], state[1], state[14], state[11]),
+        compact_column(&TD0, state[8], state[5], state[2], state[15]),
+        compact_column(&TD0, state[12], state[9], state[6], state[3]),
+    ];
+
+    for j in 0..4 {
+        let round_key = key.get_round_key(i * 4 + j);
+        state[4 * j] = w[j][0] ^ round_key[0];
+        state[4 * j + 1] = w[j][1] ^ round_key[1];
+        state[4 * j + 2] = w[j][2] ^ round_key[2];
+        state[4 * j + 3] = w[j][3] ^ round_key[3];
     }
-    temp
+    state
+}
+
+pub fn compact_merged_op<T>(mut state: State, i: usize, key: &T) -> State
+where
+    T: AESKey,
+{
+    let w = [
+        compact_column(&T0, state[0], state[5], state[10], state[15]),
+        compact_column(&T0, state[4], state[9], state[14], state[3]),
+        compact_column(&T0, state[8], state[13], state[2], state[7]),
+        compact_column(&T0, state[12], state[1], state[6], state[11]),
+    ];
+
+    for j in 0..4 {
+        let round_key = key.get_round_key(i * 4 + j);
+        state[4 * j] = w[j][0] ^ round_key[0];
+        state[4 * j + 1] = w[j][1] ^ round_key[1];
+        state[4 * j + 2] = w[j][2] ^ round_key[2];
+        state[4 * j + 3] = w[j][3] ^ round_key[3];
+    }
+    state
+}
+
+pub fn compact_sub_bytes(mut state: State) -> State {
+    for byte in state.iter_mut() {
+        *byte = FINAL_ROUND_SBOX[*byte as usize];
+    }
+    state
+}
+
+pub fn compact_inv_sub_bytes(mut state: State) -> State {
+    for byte in state.iter_mut() {
+        *byte = FINAL_ROUND_INVERSE_SBOX[*byte as usize];
+    }
+    state
+}
+
+pub fn mult_one(b: u8) -> u8 {
+    Gf256::from(b).xtime().into()
 }
 
 pub fn get_rcon(n: usize) -> u8 {
@@ -288,31 +342,50 @@ pub fn get_next_key(
     current_key: [u8; 4],
     key_length: usize,
     expansion_counter: usize,
+) -> [u8; 4] {
+    get_next_key_with(
+        prev_key,
+        current_key,
+        key_length,
+        expansion_counter,
+        sub_word,
+        get_rcon,
+    )
+}
+
+// `get_next_key` with the SubWord and round constants of another S-box and field.
+pub fn get_next_key_with(
+    prev_key: [u8; 4],
+    current_key: [u8; 4],
+    key_length: usize,
+    expansion_counter: usize,
+    sub_word: impl Fn([u8; 4]) -> [u8; 4],
+    rcon: impl Fn(usize) -> u8,
 ) -> [u8; 4] {
     let mut temp = prev_key;
 
-    if expansion_counter % key_length == 0 {
+    if expansion_counter.is_multiple_of(key_length) {
         temp = rot_word(temp);
         temp = sub_word(temp);
-        let n = expansion_counter / key_length;
-        let rcon = get_rcon(n);
-        temp[0] ^= rcon;
+        temp[0] ^= rcon(expansion_counter / key_length);
     } else if key_length > 6 && expansion_counter % key_length == 4 {
         temp = sub_word(temp);
     }
     xor_word(current_key, temp)
 }
 
+// `next_key` is `get_next_key`, or its counterpart for a custom S-box spec.
 pub fn add_round_key_no_mem(
     mut state: State,
     key_buffer: &mut [[u8; 4]; 8],
     key_expansion_counter: usize,
     key_length: usize,
+    next_key: impl Fn([u8; 4], [u8; 4], usize, usize) -> [u8; 4],
 ) -> (State, usize) {
     let mut key_expansion_counter = key_expansion_counter;
 
     for _ in 0..4 {
-        key_buffer[key_expansion_counter % key_length] = get_next_key(
+        key_buffer[key_expansion_counter % key_length] = next_key(
             key_buffer[(key_expansion_counter - 1) % key_length],
             key_buffer[key_expansion_counter % key_length],
             key_length,
@@ -324,7 +397,7 @@ pub fn add_round_key_no_mem(
     for c in 0..4 {
         let index = (key_expansion_counter + c) % key_length;
 
-        state[4 * c + 0] ^= key_buffer[index][0];
+        state[4 * c] ^= key_buffer[index][0];
         state[4 * c + 1] ^= key_buffer[index][1];
         state[4 * c + 2] ^= key_buffer[index][2];
         state[4 * c + 3] ^= key_buffer[index][3];
@@ -1,2 +1,13 @@
 pub static AES_POLYNOMIAL: u16 = 0x11B;
 pub static AES_POLYNOMIAL_DEGREE: usize = 8;
+// x^4 + x + 1, the field of the small-scale AES variants with 4-bit words.
+pub static SMALL_SCALE_POLYNOMIAL: u16 = 0x13;
+pub static SMALL_SCALE_POLYNOMIAL_DEGREE: usize = 4;
+// ShiftRows offsets C0..C3 of the rows, indexed by Nb - 4.
+pub static SHIFT_OFFSETS: [[usize; 4]; 5] = [
+    [0, 1, 2, 3],
+    [0, 1, 2, 3],
+    [0, 1, 2, 3],
+    [0, 1, 2, 4],
+    [0, 1, 3, 4],
+];
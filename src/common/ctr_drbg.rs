@@ -0,0 +1,376 @@
+// CTR_DRBG of NIST SP 800-90A Rev. 1 (section 10.2.1) with AES-128, AES-192 or
+// AES-256 and a 128-bit counter. The internal state is the key K and the counter
+// block V, seedlen = keylen + 16 bytes. Without a derivation function the entropy
+// input must be exactly seedlen bytes and is XORed with the personalization string
+// or additional input; with one, every input goes through Block_Cipher_df first.
+//
+// `CtrDrbg` implements `RngCore`, the interface of the rand_core crate, so the same
+// code can sit behind a `rand` style generator. Nothing reseeds automatically: once
+// `reseed_interval` requests have been made, `generate` fails until `reseed` is
+// called with fresh entropy.
+
+use std::fmt;
+
+use crate::{
+    common::{
+        key::{AESKey, KeyNk4, KeyNk6, KeyNk8},
+        modes::increment_counter,
+        trace::NoObserver,
+        State,
+    },
+    encrypt_state, expand_key, AESOptimization, BackendKeys,
+};
+
+// Table 3 of SP 800-90A.
+pub const MAX_RESEED_INTERVAL: u64 = 1 << 48;
+pub const MAX_BYTES_PER_REQUEST: usize = 1 << 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CtrDrbgError {
+    ReseedRequired,
+    RequestTooLarge(usize),
+    InvalidKeyLength(usize),
+    InvalidEntropyLength(usize),
+    InputTooLong(usize),
+}
+
+// The methods of rand_core::RngCore.
+pub trait RngCore {
+    fn next_u32(&mut self) -> u32;
+    fn next_u64(&mut self) -> u64;
+    fn fill_bytes(&mut self, dest: &mut [u8]);
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), CtrDrbgError>;
+}
+
+#[derive(Clone)]
+enum CipherKey {
+    Nk4(KeyNk4),
+    Nk6(KeyNk6),
+    Nk8(KeyNk8),
+}
+
+// An AES key expanded once for `optimization`, every K of the DRBG is used for
+// several blocks.
+#[derive(Clone)]
+struct BlockCipher {
+    key: CipherKey,
+    backend_keys: BackendKeys,
+    optimization: AESOptimization,
+}
+
+fn expand<T>(mut key: T, optimization: AESOptimization) -> (T, BackendKeys)
+where
+    T: AESKey,
+{
+    let backend_keys = expand_key(&mut key, optimization, false, None);
+    (key, backend_keys)
+}
+
+impl BlockCipher {
+    fn new(key: &[u8], optimization: AESOptimization) -> BlockCipher {
+        let key_data = hex::encode(key);
+        let (key, backend_keys) = match key.len() {
+            16 => {
+                let (key, backend_keys) = expand(KeyNk4::new(&key_data), optimization);
+                (CipherKey::Nk4(key), backend_keys)
+            }
+            24 => {
+                let (key, backend_keys) = expand(KeyNk6::new(&key_data), optimization);
+                (CipherKey::Nk6(key), backend_keys)
+            }
+            32 => {
+                let (key, backend_keys) = expand(KeyNk8::new(&key_data), optimization);
+                (CipherKey::Nk8(key), backend_keys)
+            }
+            length => panic!("Expected a key of 16, 24 or 32 bytes but it was {}", length),
+        };
+        BlockCipher {
+            key,
+            backend_keys,
+            optimization,
+        }
+    }
+
+    fn encrypt(&self, block: &[u8; 16]) -> [u8; 16] {
+        let state = State::from_hex_vector(block);
+        let (backend_keys, optimization) = (&self.backend_keys, self.optimization);
+        let state = match &self.key {
+            CipherKey::Nk4(key) => encrypt_state(
+                state,
+                key,
+                backend_keys,
+                optimization,
+                &mut NoObserver,
+                None,
+            ),
+            CipherKey::Nk6(key) => encrypt_state(
+                state,
+                key,
+                backend_keys,
+                optimization,
+                &mut NoObserver,
+                None,
+            ),
+            CipherKey::Nk8(key) => encrypt_state(
+                state,
+                key,
+                backend_keys,
+                optimization,
+                &mut NoObserver,
+                None,
+            ),
+        };
+        *state
+    }
+}
+
+#[derive(Clone)]
+pub struct CtrDrbg {
+    key: Vec<u8>,
+    cipher: BlockCipher,
+    v: [u8; 16],
+    reseed_counter: u64,
+    derivation_function: bool,
+    pub reseed_interval: u64,
+}
+
+// K and V are the secret state and are left out.
+impl fmt::Debug for CtrDrbg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CtrDrbg")
+            .field("key_length", &self.key.len())
+            .field("derivation_function", &self.derivation_function)
+            .field("reseed_counter", &self.reseed_counter)
+            .field("reseed_interval", &self.reseed_interval)
+            .field("optimization", &self.cipher.optimization)
+            .finish_non_exhaustive()
+    }
+}
+
+fn xor_into(target: &mut [u8], data: &[u8]) {
+    for (t, d) in target.iter_mut().zip(data.iter()) {
+        *t ^= d;
+    }
+}
+
+// CBC-MAC with a zero IV.
+fn bcc(cipher: &BlockCipher, data: &[u8]) -> [u8; 16] {
+    data.chunks(16).fold([0u8; 16], |chaining, block| {
+        let mut input = chaining;
+        xor_into(&mut input, block);
+        cipher.encrypt(&input)
+    })
+}
+
+fn block_cipher_df(
+    key_length: usize,
+    input: &[u8],
+    bytes: usize,
+    optimization: AESOptimization,
+) -> Vec<u8> {
+    // S = L || N || input || 0x80, padded with zeros to whole blocks.
+    let mut s = Vec::with_capacity(input.len() + 25);
+    s.extend_from_slice(&(input.len() as u32).to_be_bytes());
+    s.extend_from_slice(&(bytes as u32).to_be_bytes());
+    s.extend_from_slice(input);
+    s.push(0x80);
+    s.resize(s.len().div_ceil(16) * 16, 0);
+
+    let df_key: Vec<u8> = (0..key_length as u8).collect();
+    let df_cipher = BlockCipher::new(&df_key, optimization);
+    let mut temp = Vec::with_capacity(key_length + 32);
+    let mut i: u32 = 0;
+    while temp.len() < key_length + 16 {
+        let mut data = i.to_be_bytes().to_vec();
+        data.resize(16, 0);
+        data.extend_from_slice(&s);
+        temp.extend_from_slice(&bcc(&df_cipher, &data));
+        i += 1;
+    }
+
+    let cipher = BlockCipher::new(&temp[..key_length], optimization);
+    let mut x: [u8; 16] = temp[key_length..key_length + 16]
+        .try_into()
+        .unwrap_or_else(|_| panic!("Expected a block of 16 bytes"));
+    let mut output = Vec::with_capacity(bytes + 16);
+    while output.len() < bytes {
+        x = cipher.encrypt(&x);
+        output.extend_from_slice(&x);
+    }
+    output.truncate(bytes);
+    output
+}
+
+impl CtrDrbg {
+    // `key_length` in bytes: 16, 24 or 32. With a derivation function the entropy
+    // input needs at least keylen bytes, without one exactly seedlen bytes and the
+    // nonce is not used.
+    pub fn new(
+        key_length: usize,
+        derivation_function: bool,
+        entropy_input: &[u8],
+        nonce: &[u8],
+        personalization_string: &[u8],
+    ) -> Result<CtrDrbg, CtrDrbgError> {
+        if ![16, 24, 32].contains(&key_length) {
+            return Err(CtrDrbgError::InvalidKeyLength(key_length));
+        }
+        let key = vec![0; key_length];
+        let mut drbg = CtrDrbg {
+            cipher: BlockCipher::new(&key, AESOptimization::NoOptimization),
+            key,
+            v: [0; 16],
+            reseed_counter: 0,
+            derivation_function,
+            reseed_interval: MAX_RESEED_INTERVAL,
+        };
+        let mut input = entropy_input.to_vec();
+        if derivation_function {
+            input.extend_from_slice(nonce);
+        }
+        input.extend_from_slice(personalization_string);
+        let seed_material = drbg.seed_material(entropy_input.len(), &input)?;
+        drbg.update(&seed_material);
+        drbg.reseed_counter = 1;
+        Ok(drbg)
+    }
+
+    pub fn seed_length(&self) -> usize {
+        self.key.len() + 16
+    }
+
+    pub fn reseed_counter(&self) -> u64 {
+        self.reseed_counter
+    }
+
+    pub fn optimization(&self) -> AESOptimization {
+        self.cipher.optimization
+    }
+
+    // The output does not depend on the optimization, only how AES is computed.
+    pub fn set_optimization(&mut self, optimization: AESOptimization) {
+        self.cipher = BlockCipher::new(&self.key, optimization);
+    }
+
+    // `input` is entropy_input || the rest; without a derivation function the rest
+    // (at most seedlen bytes) is XORed into the entropy input.
+    fn seed_material(&self, entropy_length: usize, input: &[u8]) -> Result<Vec<u8>, CtrDrbgError> {
+        let seed_length = self.seed_length();
+        if self.derivation_function {
+            if entropy_length < self.key.len() {
+                return Err(CtrDrbgError::InvalidEntropyLength(entropy_length));
+            }
+            return Ok(block_cipher_df(
+                self.key.len(),
+                input,
+                seed_length,
+                self.optimization(),
+            ));
+        }
+        if entropy_length != seed_length {
+            return Err(CtrDrbgError::InvalidEntropyLength(entropy_length));
+        }
+        let (entropy_input, rest) = input.split_at(entropy_length);
+        if rest.len() > seed_length {
+            return Err(CtrDrbgError::InputTooLong(rest.len()));
+        }
+        let mut seed_material = entropy_input.to_vec();
+        xor_into(&mut seed_material, rest);
+        Ok(seed_material)
+    }
+
+    // CTR_DRBG_Update: seedlen bytes of keystream XOR `provided_data` become K || V,
+    // the new K is expanded once here.
+    fn update(&mut self, provided_data: &[u8]) {
+        let key_length = self.key.len();
+        let mut temp = Vec::with_capacity(self.seed_length() + 16);
+        while temp.len() < self.seed_length() {
+            increment_counter(&mut self.v);
+            temp.extend_from_slice(&self.cipher.encrypt(&self.v));
+        }
+        temp.truncate(self.seed_length());
+        xor_into(&mut temp, provided_data);
+        self.key = temp[..key_length].to_vec();
+        self.v.copy_from_slice(&temp[key_length..]);
+        self.cipher = BlockCipher::new(&self.key, self.cipher.optimization);
+    }
+
+    pub fn reseed(
+        &mut self,
+        entropy_input: &[u8],
+        additional_input: &[u8],
+    ) -> Result<(), CtrDrbgError> {
+        let mut input = entropy_input.to_vec();
+        input.extend_from_slice(additional_input);
+        let seed_material = self.seed_material(entropy_input.len(), &input)?;
+        self.update(&seed_material);
+        self.reseed_counter = 1;
+        Ok(())
+    }
+
+    // Fills `output` (at most 2^19 bits) and then updates the state with the
+    // additional input.
+    pub fn generate(
+        &mut self,
+        output: &mut [u8],
+        additional_input: &[u8],
+    ) -> Result<(), CtrDrbgError> {
+        if output.len() > MAX_BYTES_PER_REQUEST {
+            return Err(CtrDrbgError::RequestTooLarge(output.len()));
+        }
+        if self.reseed_counter > self.reseed_interval {
+            return Err(CtrDrbgError::ReseedRequired);
+        }
+        let mut additional = vec![0u8; self.seed_length()];
+        if !additional_input.is_empty() {
+            if self.derivation_function {
+                additional = block_cipher_df(
+                    self.key.len(),
+                    additional_input,
+                    self.seed_length(),
+                    self.optimization(),
+                );
+            } else if additional_input.len() > self.seed_length() {
+                return Err(CtrDrbgError::InputTooLong(additional_input.len()));
+            } else {
+                xor_into(&mut additional, additional_input);
+            }
+            self.update(&additional);
+        }
+        for chunk in output.chunks_mut(16) {
+            increment_counter(&mut self.v);
+            let block = self.cipher.encrypt(&self.v);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        self.update(&additional);
+        self.reseed_counter += 1;
+        Ok(())
+    }
+}
+
+impl RngCore for CtrDrbg {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.try_fill_bytes(dest)
+            .unwrap_or_else(|error| panic!("CTR_DRBG failed to generate: {:?}", error))
+    }
+
+    // Longer requests are split into several generate calls.
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), CtrDrbgError> {
+        for chunk in dest.chunks_mut(MAX_BYTES_PER_REQUEST) {
+            self.generate(chunk, &[])?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+// Elements of GF(2^8) = GF(2)[x] / (x^8 + x^4 + x^3 + x + 1). Addition is XOR,
+// multiplication always runs the same eight shift-and-add steps and inversion is
+// a^254 with a fixed exponent, so no branch or memory access depends on the values.
+
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Sub};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Gf256(pub u8);
+
+impl Gf256 {
+    pub const ZERO: Gf256 = Gf256(0);
+    pub const ONE: Gf256 = Gf256(1);
+
+    pub const fn new(value: u8) -> Gf256 {
+        Gf256(value)
+    }
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    // Multiplication by x, the reduction is selected with a mask instead of a branch.
+    pub const fn xtime(self) -> Gf256 {
+        Gf256((self.0 << 1) ^ (0x1b & 0u8.wrapping_sub(self.0 >> 7)))
+    }
+
+    pub const fn multiply(self, rhs: Gf256) -> Gf256 {
+        let mut result = 0;
+        let mut a = self;
+        let mut b = rhs.0;
+        let mut i = 0;
+        while i < 8 {
+            result ^= a.0 & 0u8.wrapping_sub(b & 1);
+            a = a.xtime();
+            b >>= 1;
+            i += 1;
+        }
+        Gf256(result)
+    }
+
+    // The exponent is public, only its bits decide which multiplications are done.
+    pub const fn pow(self, mut exponent: usize) -> Gf256 {
+        let mut result = Gf256::ONE;
+        let mut square = self;
+        while exponent > 0 {
+            if exponent % 2 == 1 {
+                result = result.multiply(square);
+            }
+            square = square.multiply(square);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    // a^254 = a^-1 for a != 0 and 0^254 = 0, which is what the S-box expects.
+    pub const fn inv(self) -> Gf256 {
+        self.pow(254)
+    }
+}
+
+impl From<u8> for Gf256 {
+    fn from(value: u8) -> Self {
+        Gf256(value)
+    }
+}
+
+impl From<Gf256> for u8 {
+    fn from(value: Gf256) -> Self {
+        value.0
+    }
+}
+
+impl Add for Gf256 {
+    type Output = Gf256;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn add(self, rhs: Gf256) -> Gf256 {
+        Gf256(self.0 ^ rhs.0)
+    }
+}
+
+impl Sub for Gf256 {
+    type Output = Gf256;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn sub(self, rhs: Gf256) -> Gf256 {
+        Gf256(self.0 ^ rhs.0)
+    }
+}
+
+impl Mul for Gf256 {
+    type Output = Gf256;
+
+    fn mul(self, rhs: Gf256) -> Gf256 {
+        self.multiply(rhs)
+    }
+}
+
+impl Div for Gf256 {
+    type Output = Gf256;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: Gf256) -> Gf256 {
+        if rhs == Gf256::ZERO {
+            panic!("Division by zero in GF(2^8)");
+        }
+        self.multiply(rhs.inv())
+    }
+}
+
+impl AddAssign for Gf256 {
+    fn add_assign(&mut self, rhs: Gf256) {
+        *self = *self + rhs;
+    }
+}
+
+impl MulAssign for Gf256 {
+    fn mul_assign(&mut self, rhs: Gf256) {
+        *self = *self * rhs;
+    }
+}
@@ -4,7 +4,8 @@ use crate::common::{
 };
 
 use super::{
-    cipher_operations::{inv_mix_columns, sub_word},
+    cipher_operations::{get_rcon, inv_mix_columns, sub_word},
+    rijndael::rijndael_num_rounds,
     utils::decode_to_hex_vector,
 };
 
@@ -31,27 +32,38 @@ pub trait AESKey {
     }
 
     fn key_expansion(&mut self, inverse: bool) {
-        self.init_round_keys();
-        let mut i = self.key_length();
+        self.key_expansion_with(sub_word);
+        if inverse {
+            self.mix_key_columns();
+        }
+    }
+    fn key_expansion_with(&mut self, sub_word: impl Fn([u8; 4]) -> [u8; 4]) {
+        for (i, word) in self.expand_key_words(4, sub_word).into_iter().enumerate() {
+            self.set_round_key(i, word);
+        }
+    }
+    // The Nb * (Nr + 1) words of the schedule for a block of Nb words, the key only
+    // stores the AES (Nb = 4) schedule.
+    fn expand_key_words(
+        &self,
+        block_words: usize,
+        sub_word: impl Fn([u8; 4]) -> [u8; 4],
+    ) -> Vec<[u8; 4]> {
+        let key_length = self.key_length();
+        let mut words: Vec<[u8; 4]> = (0..key_length).map(|i| self.get_key_as_row(i)).collect();
 
-        let rcons = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
-        let mut rcoun_counter = 0;
-        while i < 4 * (self.num_rounds() + 1) {
-            let mut temp = self.get_round_key(i - 1);
+        for i in key_length..block_words * (rijndael_num_rounds(key_length, block_words) + 1) {
+            let mut temp = words[i - 1];
 
-            if i % self.key_length() == 0 {
+            if i % key_length == 0 {
                 temp = sub_word(rot_word(temp));
-                temp = xor_word(temp, [rcons[rcoun_counter], 0, 0, 0]);
-                rcoun_counter += 1;
-            } else if self.key_length() > 6 && i % self.key_length() == 4 {
+                temp = xor_word(temp, [get_rcon(i / key_length), 0, 0, 0]);
+            } else if key_length > 6 && i % key_length == 4 {
                 temp = sub_word(temp);
             }
-            self.set_round_key(i, xor_word(self.get_round_key(i - self.key_length()), temp));
-            i += 1;
-        }
-        if inverse {
-            self.mix_key_columns();
+            words.push(xor_word(words[i - key_length], temp));
         }
+        words
     }
     fn get_round_subkey(&self, round: usize) -> [[u8; 4]; 4] {
         [
@@ -94,12 +106,24 @@ pub struct KeyNk4 {
     data: [u8; 16],
     round_keys: [[u8; 4]; 44],
 }
+// Nk = 5 and 7 are Rijndael key lengths that AES did not adopt. With the AES block
+// (Nb = 4) they use Nr = max(Nk, Nb) + 6 = 11 and 13 rounds.
+#[derive(Copy, Clone)]
+pub struct KeyNk5 {
+    data: [u8; 20],
+    round_keys: [[u8; 4]; 48],
+}
 #[derive(Copy, Clone)]
 pub struct KeyNk6 {
     data: [u8; 24],
     round_keys: [[u8; 4]; 72],
 }
 #[derive(Copy, Clone)]
+pub struct KeyNk7 {
+    data: [u8; 28],
+    round_keys: [[u8; 4]; 56],
+}
+#[derive(Copy, Clone)]
 pub struct KeyNk8 {
     data: [u8; 32],
     round_keys: [[u8; 4]; 120],
@@ -117,6 +141,18 @@ impl KeyNk4 {
         }
     }
 }
+impl KeyNk5 {
+    pub fn new(key_data: &str) -> KeyNk5 {
+        KeyNk5 {
+            data: decode_to_hex_vector(key_data)
+                .try_into()
+                .unwrap_or_else(|v: Vec<u8>| {
+                    panic!("Expected a Vec of length {} but it was {}", 20, v.len())
+                }),
+            round_keys: [[u8::default(); 4]; 48],
+        }
+    }
+}
 impl KeyNk6 {
     pub fn new(key_data: &str) -> KeyNk6 {
         KeyNk6 {
@@ -129,6 +165,18 @@ impl KeyNk6 {
         }
     }
 }
+impl KeyNk7 {
+    pub fn new(key_data: &str) -> KeyNk7 {
+        KeyNk7 {
+            data: decode_to_hex_vector(key_data)
+                .try_into()
+                .unwrap_or_else(|v: Vec<u8>| {
+                    panic!("Expected a Vec of length {} but it was {}", 28, v.len())
+                }),
+            round_keys: [[u8::default(); 4]; 56],
+        }
+    }
+}
 impl KeyNk8 {
     pub fn new(key_data: &str) -> KeyNk8 {
         KeyNk8 {
@@ -163,6 +211,28 @@ impl AESKey for KeyNk4 {
         self.mix_round_key::<10>(&mut states);
     }
 }
+impl AESKey for KeyNk5 {
+    fn num_rounds(&self) -> usize {
+        11
+    }
+    fn get_round_key(&self, i: usize) -> [u8; 4] {
+        self.round_keys[i]
+    }
+    fn key_length(&self) -> usize {
+        5
+    }
+    fn set_round_key(&mut self, i: usize, value: [u8; 4]) {
+        self.round_keys[i] = value;
+    }
+
+    fn get_key_at(&self, i: usize) -> u8 {
+        self.data[i]
+    }
+    fn mix_key_columns(&mut self) {
+        let mut states = self.key_to_state::<11>();
+        self.mix_round_key::<11>(&mut states);
+    }
+}
 impl AESKey for KeyNk6 {
     fn set_round_key(&mut self, i: usize, value: [u8; 4]) {
         self.round_keys[i] = value;
@@ -185,6 +255,28 @@ impl AESKey for KeyNk6 {
         6
     }
 }
+impl AESKey for KeyNk7 {
+    fn num_rounds(&self) -> usize {
+        13
+    }
+    fn get_round_key(&self, i: usize) -> [u8; 4] {
+        self.round_keys[i]
+    }
+    fn key_length(&self) -> usize {
+        7
+    }
+    fn set_round_key(&mut self, i: usize, value: [u8; 4]) {
+        self.round_keys[i] = value;
+    }
+
+    fn get_key_at(&self, i: usize) -> u8 {
+        self.data[i]
+    }
+    fn mix_key_columns(&mut self) {
+        let mut states = self.key_to_state::<13>();
+        self.mix_round_key::<13>(&mut states);
+    }
+}
 impl AESKey for KeyNk8 {
     fn mix_key_columns(&mut self) {
         let mut states = self.key_to_state::<14>();
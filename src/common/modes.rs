@@ -1,30 +1,302 @@
 use crate::AESKey;
-use crate::{encrypt_block, AESOptimization};
+use crate::{decrypt_block, encrypt_block, AESOptimization};
 
+use super::aes_ni::{hardware_decrypt_blocks, hardware_encrypt_blocks};
 use super::padding::pad_message_pkcs7;
+use super::utils::decode_to_hex_vector;
 
-pub fn encrypt_ecb<'a, T>(message: &'a str, mut key: T) -> String
+// The hardware path hands all blocks to AES-NI at once so they can be interleaved,
+// the other optimizations go through `encrypt_block` one block at a time.
+fn encrypt_blocks<T>(blocks: &mut [[u8; 16]], mut key: T, optimization: AESOptimization)
+where
+    T: AESKey + Copy,
+{
+    if optimization == AESOptimization::Hardware {
+        hardware_encrypt_blocks(blocks, &mut key);
+        return;
+    }
+    for block in blocks.iter_mut() {
+        *block = to_block(&encrypt_block(&hex::encode(*block), key, optimization));
+    }
+}
+
+fn decrypt_blocks<T>(blocks: &mut [[u8; 16]], mut key: T, optimization: AESOptimization)
+where
+    T: AESKey + Copy,
+{
+    if optimization == AESOptimization::Hardware {
+        hardware_decrypt_blocks(blocks, &mut key);
+        return;
+    }
+    for block in blocks.iter_mut() {
+        *block = to_block(&decrypt_block(&hex::encode(*block), key, optimization));
+    }
+}
+
+fn to_block(message: &str) -> [u8; 16] {
+    decode_to_hex_vector(message)
+        .try_into()
+        .unwrap_or_else(|v: Vec<u8>| {
+            panic!("Expected a Vec of length {} but it was {}", 16, v.len())
+        })
+}
+
+fn xor_block(x: [u8; 16], y: [u8; 16]) -> [u8; 16] {
+    let mut result = x;
+    for (r, b) in result.iter_mut().zip(y.iter()) {
+        *r ^= b;
+    }
+    result
+}
+
+pub fn encrypt_ecb<T>(message: &str, key: T) -> String
+where
+    T: AESKey + Copy,
+{
+    encrypt_ecb_with_optimization(message, key, AESOptimization::NoOptimization)
+}
+
+pub fn encrypt_ecb_with_optimization<T>(
+    message: &str,
+    key: T,
+    optimization: AESOptimization,
+) -> String
 where
     T: AESKey + Copy,
 {
     let padded_message = pad_message_pkcs7(message, 16);
-    let mut ciphertext: String = "".to_owned();
-    for i in (0..padded_message.len()).step_by(32) {
-        let result = encrypt_block(
-            &padded_message[i..i + 32],
-            key,
-            AESOptimization::NoOptimization,
+    let mut blocks: Vec<[u8; 16]> = decode_to_hex_vector(&padded_message)
+        .chunks(16)
+        .map(|block| block.try_into().unwrap())
+        .collect();
+    encrypt_blocks(&mut blocks, key, optimization);
+    hex::encode(blocks.concat())
+}
+
+// The counter block is a 128-bit big-endian integer, as in SP 800-38A appendix B.1.
+pub fn increment_counter(block: &mut [u8; 16]) {
+    for byte in block.iter_mut().rev() {
+        *byte = byte.wrapping_add(1);
+        if *byte != 0 {
+            break;
+        }
+    }
+}
+
+pub fn encrypt_ctr<T>(message: &str, key: T, initial_counter: &str) -> String
+where
+    T: AESKey + Copy,
+{
+    encrypt_ctr_with_optimization(
+        message,
+        key,
+        initial_counter,
+        AESOptimization::NoOptimization,
+    )
+}
+
+// No padding, the last keystream block is cut to the length of the message.
+pub fn encrypt_ctr_with_optimization<T>(
+    message: &str,
+    key: T,
+    initial_counter: &str,
+    optimization: AESOptimization,
+) -> String
+where
+    T: AESKey + Copy,
+{
+    let message = decode_to_hex_vector(message);
+    let mut counter = to_block(initial_counter);
+    let mut keystream: Vec<[u8; 16]> = (0..message.len().div_ceil(16))
+        .map(|_| {
+            let block = counter;
+            increment_counter(&mut counter);
+            block
+        })
+        .collect();
+    encrypt_blocks(&mut keystream, key, optimization);
+    let ciphertext: Vec<u8> = message
+        .iter()
+        .zip(keystream.iter().flatten())
+        .map(|(m, k)| m ^ k)
+        .collect();
+    hex::encode(ciphertext)
+}
+
+// CTR decryption is the same keystream XOR.
+pub fn decrypt_ctr<T>(ciphertext: &str, key: T, initial_counter: &str) -> String
+where
+    T: AESKey + Copy,
+{
+    encrypt_ctr(ciphertext, key, initial_counter)
+}
+
+// Multiplication of a tweak by alpha = x in GF(2^128), little-endian as in IEEE 1619.
+fn mul_alpha(tweak: [u8; 16]) -> [u8; 16] {
+    let mut result = [0u8; 16];
+    let mut carry = 0;
+    for (r, t) in result.iter_mut().zip(tweak.iter()) {
+        *r = (t << 1) | carry;
+        carry = t >> 7;
+    }
+    if carry == 1 {
+        result[0] ^= 0x87;
+    }
+    result
+}
+
+fn xts_tweaks<T>(
+    tweak_key: T,
+    tweak: &str,
+    count: usize,
+    optimization: AESOptimization,
+) -> Vec<[u8; 16]>
+where
+    T: AESKey + Copy,
+{
+    let mut first = [to_block(tweak)];
+    encrypt_blocks(&mut first, tweak_key, optimization);
+    let mut tweaks = Vec::with_capacity(count);
+    let mut current = first[0];
+    for _ in 0..count {
+        tweaks.push(current);
+        current = mul_alpha(current);
+    }
+    tweaks
+}
+
+// C = E(P ^ T) ^ T for all blocks in one batch.
+fn xts_blocks<T>(
+    blocks: &mut [[u8; 16]],
+    tweaks: &[[u8; 16]],
+    key: T,
+    optimization: AESOptimization,
+    inverse: bool,
+) where
+    T: AESKey + Copy,
+{
+    for (block, tweak) in blocks.iter_mut().zip(tweaks.iter()) {
+        *block = xor_block(*block, *tweak);
+    }
+    if inverse {
+        decrypt_blocks(blocks, key, optimization);
+    } else {
+        encrypt_blocks(blocks, key, optimization);
+    }
+    for (block, tweak) in blocks.iter_mut().zip(tweaks.iter()) {
+        *block = xor_block(*block, *tweak);
+    }
+}
+
+fn xts_units(data: &[u8]) -> (Vec<[u8; 16]>, &[u8]) {
+    if data.len() < 16 {
+        panic!(
+            "Expected at least 16 bytes for XTS but it was {}",
+            data.len()
         );
-        ciphertext += &result;
     }
+    let full = data.len() / 16;
+    let blocks = data[..16 * full]
+        .chunks(16)
+        .map(|block| block.try_into().unwrap())
+        .collect();
+    (blocks, &data[16 * full..])
+}
+
+pub fn encrypt_xts<T>(message: &str, data_key: T, tweak_key: T, tweak: &str) -> String
+where
+    T: AESKey + Copy,
+{
+    encrypt_xts_with_optimization(
+        message,
+        data_key,
+        tweak_key,
+        tweak,
+        AESOptimization::NoOptimization,
+    )
+}
+
+// XTS-AES of IEEE 1619 with ciphertext stealing for a partial last block. `tweak` is
+// the 16-byte data unit tweak, the sequence number in little-endian byte order.
+pub fn encrypt_xts_with_optimization<T>(
+    message: &str,
+    data_key: T,
+    tweak_key: T,
+    tweak: &str,
+    optimization: AESOptimization,
+) -> String
+where
+    T: AESKey + Copy,
+{
+    let message = decode_to_hex_vector(message);
+    let (mut blocks, tail) = xts_units(&message);
+    let full = blocks.len();
+    let tweaks = xts_tweaks(tweak_key, tweak, full + 1, optimization);
+    xts_blocks(&mut blocks, &tweaks, data_key, optimization, false);
+
+    let mut stolen = Vec::new();
+    if !tail.is_empty() {
+        let last = blocks[full - 1];
+        stolen.extend_from_slice(&last[..tail.len()]);
+        let mut partial = [last];
+        partial[0][..tail.len()].copy_from_slice(tail);
+        xts_blocks(&mut partial, &tweaks[full..], data_key, optimization, false);
+        blocks[full - 1] = partial[0];
+    }
+    hex::encode(blocks.concat()) + &hex::encode(stolen)
+}
 
-    ciphertext
+pub fn decrypt_xts<T>(ciphertext: &str, data_key: T, tweak_key: T, tweak: &str) -> String
+where
+    T: AESKey + Copy,
+{
+    decrypt_xts_with_optimization(
+        ciphertext,
+        data_key,
+        tweak_key,
+        tweak,
+        AESOptimization::NoOptimization,
+    )
+}
+
+pub fn decrypt_xts_with_optimization<T>(
+    ciphertext: &str,
+    data_key: T,
+    tweak_key: T,
+    tweak: &str,
+    optimization: AESOptimization,
+) -> String
+where
+    T: AESKey + Copy,
+{
+    let ciphertext = decode_to_hex_vector(ciphertext);
+    let (mut blocks, tail) = xts_units(&ciphertext);
+    let full = blocks.len();
+    let tweaks = xts_tweaks(tweak_key, tweak, full + 1, optimization);
+    // With stealing, the last full ciphertext block was encrypted with the next tweak.
+    let batch = if tail.is_empty() { full } else { full - 1 };
+    xts_blocks(&mut blocks[..batch], &tweaks, data_key, optimization, true);
+
+    let mut stolen = Vec::new();
+    if !tail.is_empty() {
+        let mut partial = [blocks[full - 1]];
+        xts_blocks(&mut partial, &tweaks[full..], data_key, optimization, true);
+        stolen.extend_from_slice(&partial[0][..tail.len()]);
+        partial[0][..tail.len()].copy_from_slice(tail);
+        xts_blocks(
+            &mut partial,
+            &tweaks[full - 1..],
+            data_key,
+            optimization,
+            true,
+        );
+        blocks[full - 1] = partial[0];
+    }
+    hex::encode(blocks.concat()) + &hex::encode(stolen)
 }
 //pub fn decrypt_ecb<'a, T>(message: &'a str, mut key: T) -> String {}
 //pub fn encrypt_cbc<'a, T>(message: &'a str, mut key: T) -> String {}
 //pub fn decrypt_cbc<'a, T>(message: &'a str, mut key: T) -> String {}
-//pub fn encrypt_ctr<'a, T>(message: &'a str, mut key: T) -> String {}
-//pub fn decrypt_ctr<'a, T>(message: &'a str, mut key: T) -> String {}
 //pub fn encrypt_gcm<'a, T>(message: &'a str, mut key: T) -> String {}
 //pub fn decrypt_gcm<'a, T>(message: &'a str, mut key: T) -> String {}
 //pub fn encrypt_ofb<'a, T>(message: &'a str, mut key: T) -> String {}
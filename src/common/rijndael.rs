@@ -0,0 +1,137 @@
+// Rijndael with block sizes of Nb = 4..8 words (128 to 256 bits). AES is the
+// Nb = 4 subset, the other block sizes are kept for legacy data. The number of
+// rounds is Nr = max(Nk, Nb) + 6 and the key schedule yields Nb * (Nr + 1) words.
+
+use crate::common::{
+    cipher_operations::{
+        add_round_key, inv_mix_columns, inv_shift_rows, inv_sub_bytes, mix_columns, sub_bytes,
+        sub_rows, sub_word,
+    },
+    key::AESKey,
+    utils::decode_to_hex_vector,
+    State,
+};
+
+pub const MIN_BLOCK_WORDS: usize = 4;
+pub const MAX_BLOCK_WORDS: usize = 8;
+
+pub fn rijndael_num_rounds(key_words: usize, block_words: usize) -> usize {
+    key_words.max(block_words) + 6
+}
+
+fn check_block_words(block_words: usize) {
+    if !(MIN_BLOCK_WORDS..=MAX_BLOCK_WORDS).contains(&block_words) {
+        panic!(
+            "Expected a block of {} to {} words but it was {}",
+            MIN_BLOCK_WORDS, MAX_BLOCK_WORDS, block_words
+        );
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Rijndael {
+    block_words: usize,
+    num_rounds: usize,
+    round_keys: Vec<[u8; 4]>,
+}
+
+impl Rijndael {
+    pub fn new<T>(key: &T, block_words: usize) -> Rijndael
+    where
+        T: AESKey,
+    {
+        check_block_words(block_words);
+        Rijndael {
+            block_words,
+            num_rounds: rijndael_num_rounds(key.key_length(), block_words),
+            round_keys: key.expand_key_words(block_words, sub_word),
+        }
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.num_rounds
+    }
+
+    pub fn block_words(&self) -> usize {
+        self.block_words
+    }
+
+    pub fn get_round_key(&self, i: usize) -> [u8; 4] {
+        self.round_keys[i]
+    }
+
+    fn round_key(&self, round: usize) -> &[[u8; 4]] {
+        &self.round_keys[round * self.block_words..(round + 1) * self.block_words]
+    }
+
+    pub fn encrypt_state<const N: usize>(&self, mut state: State<N>) -> State<N> {
+        self.check_state(&state);
+        add_round_key(&mut state, self.round_key(0));
+        for round in 1..self.num_rounds {
+            state = mix_columns(sub_rows(sub_bytes(state)));
+            add_round_key(&mut state, self.round_key(round));
+        }
+        state = sub_rows(sub_bytes(state));
+        add_round_key(&mut state, self.round_key(self.num_rounds));
+        state
+    }
+
+    pub fn decrypt_state<const N: usize>(&self, mut state: State<N>) -> State<N> {
+        self.check_state(&state);
+        add_round_key(&mut state, self.round_key(self.num_rounds));
+        for round in (1..self.num_rounds).rev() {
+            state = inv_sub_bytes(inv_shift_rows(state));
+            add_round_key(&mut state, self.round_key(round));
+            inv_mix_columns(&mut state);
+        }
+        state = inv_sub_bytes(inv_shift_rows(state));
+        add_round_key(&mut state, self.round_key(0));
+        state
+    }
+
+    fn check_state<const N: usize>(&self, state: &State<N>) {
+        if state.block_words() != self.block_words {
+            panic!(
+                "Expected a block of {} words but it was {}",
+                self.block_words,
+                state.block_words()
+            );
+        }
+    }
+
+    fn run_block<const N: usize>(&self, block: &[u8], inverse: bool) -> String {
+        let state = State::<N>::from_hex_vector(block.try_into().unwrap());
+        let state = if inverse {
+            self.decrypt_state(state)
+        } else {
+            self.encrypt_state(state)
+        };
+        hex::encode(*state)
+    }
+
+    fn run_hex_block(&self, data: &str, inverse: bool) -> String {
+        let block = decode_to_hex_vector(data);
+        if block.len() != 4 * self.block_words {
+            panic!(
+                "Expected a block of {} bytes but it was {}",
+                4 * self.block_words,
+                block.len()
+            );
+        }
+        match self.block_words {
+            4 => self.run_block::<16>(&block, inverse),
+            5 => self.run_block::<20>(&block, inverse),
+            6 => self.run_block::<24>(&block, inverse),
+            7 => self.run_block::<28>(&block, inverse),
+            _ => self.run_block::<32>(&block, inverse),
+        }
+    }
+
+    pub fn encrypt_block(&self, message: &str) -> String {
+        self.run_hex_block(message, false)
+    }
+
+    pub fn decrypt_block(&self, ciphertext: &str) -> String {
+        self.run_hex_block(ciphertext, true)
+    }
+}
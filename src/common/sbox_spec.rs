@@ -0,0 +1,303 @@
+// Rijndael-like ciphers over another irreducible polynomial and / or another
+// affine map, for classroom experiments. `SBoxSpec::aes()` gives back AES.
+//
+// The affine map is circulant: bit k of `affine_mask` adds q <<< k, AES uses
+// q ^ q <<< 1 ^ q <<< 2 ^ q <<< 3 ^ q <<< 4 ^ 0x63, i.e. mask 0x1f and constant 0x63.
+
+use crate::common::{
+    cipher_operations::{get_next_key_with, inverse_with, mult_with},
+    constants::{AES_POLYNOMIAL, AES_POLYNOMIAL_DEGREE},
+    key::AESKey,
+    State,
+};
+use crate::AESOptimization;
+
+fn polynomial_degree(polynomial: u16) -> u32 {
+    15 - polynomial.leading_zeros()
+}
+
+fn polynomial_remainder(mut polynomial: u16, divisor: u16) -> u16 {
+    let divisor_degree = polynomial_degree(divisor);
+    while polynomial != 0 && polynomial_degree(polynomial) >= divisor_degree {
+        polynomial ^= divisor << (polynomial_degree(polynomial) - divisor_degree);
+    }
+    polynomial
+}
+
+// Trial division by every polynomial of degree 1 up to half the degree.
+pub fn is_irreducible(polynomial: u16) -> bool {
+    if polynomial < 2 {
+        return false;
+    }
+    let degree = polynomial_degree(polynomial);
+    (2u16..1 << (degree / 2 + 1)).all(|divisor| polynomial_remainder(polynomial, divisor) != 0)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FieldParams {
+    polynomial: u16,
+}
+
+impl FieldParams {
+    pub fn new(polynomial: u16) -> FieldParams {
+        if polynomial_degree(polynomial) as usize != AES_POLYNOMIAL_DEGREE {
+            panic!(
+                "Expected a polynomial of degree {} but it was {:#x}",
+                AES_POLYNOMIAL_DEGREE, polynomial
+            );
+        }
+        if !is_irreducible(polynomial) {
+            panic!("{:#x} is not an irreducible polynomial", polynomial);
+        }
+        FieldParams { polynomial }
+    }
+    pub fn aes() -> FieldParams {
+        FieldParams::new(AES_POLYNOMIAL)
+    }
+    pub fn polynomial(&self) -> u16 {
+        self.polynomial
+    }
+    pub fn mult(&self, a: u8, b: u8) -> u8 {
+        mult_with(a, b, self.polynomial, AES_POLYNOMIAL_DEGREE)
+    }
+    pub fn inverse(&self, a: u8) -> u8 {
+        inverse_with(a, self.polynomial, AES_POLYNOMIAL_DEGREE)
+    }
+}
+
+fn circulant(mask: u8, q: u8) -> u8 {
+    (0..8)
+        .filter(|k| (mask >> k) & 1 == 1)
+        .fold(0, |acc, k| acc ^ q.rotate_left(k))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SBoxSpec {
+    field: FieldParams,
+    affine_mask: u8,
+    affine_constant: u8,
+    inverse_affine_mask: u8,
+    inverse_affine_constant: u8,
+}
+
+impl SBoxSpec {
+    pub fn new(field: FieldParams, affine_mask: u8, affine_constant: u8) -> SBoxSpec {
+        let inverse_affine_mask = (0..=255u8)
+            .find(|inverse| circulant(*inverse, circulant(affine_mask, 1)) == 1)
+            .unwrap_or_else(|| panic!("The affine mask {:#x} is not invertible", affine_mask));
+
+        SBoxSpec {
+            field,
+            affine_mask,
+            affine_constant,
+            inverse_affine_mask,
+            inverse_affine_constant: circulant(inverse_affine_mask, affine_constant),
+        }
+    }
+    pub fn aes() -> SBoxSpec {
+        SBoxSpec::new(FieldParams::aes(), 0x1f, 0x63)
+    }
+    pub fn field(&self) -> FieldParams {
+        self.field
+    }
+    pub fn inverse_affine(&self) -> (u8, u8) {
+        (self.inverse_affine_mask, self.inverse_affine_constant)
+    }
+
+    pub fn sub_byte(&self, byte: u8) -> u8 {
+        circulant(self.affine_mask, self.field.inverse(byte)) ^ self.affine_constant
+    }
+    pub fn inv_sub_byte(&self, byte: u8) -> u8 {
+        self.field
+            .inverse(circulant(self.inverse_affine_mask, byte) ^ self.inverse_affine_constant)
+    }
+
+    pub fn sbox_table(&self) -> [[u8; 16]; 16] {
+        self.table(|byte| self.sub_byte(byte))
+    }
+    pub fn inverse_sbox_table(&self) -> [[u8; 16]; 16] {
+        self.table(|byte| self.inv_sub_byte(byte))
+    }
+    fn table(&self, f: impl Fn(u8) -> u8) -> [[u8; 16]; 16] {
+        let mut table = [[0u8; 16]; 16];
+        for (x, row) in table.iter_mut().enumerate() {
+            for (y, entry) in row.iter_mut().enumerate() {
+                *entry = f((x * 16 + y) as u8);
+            }
+        }
+        table
+    }
+
+    // x^(n - 1) in the spec's field, only n > 8 depends on the polynomial.
+    pub fn rcon(&self, n: usize) -> u8 {
+        (1..n).fold(1, |rcon, _| self.field.mult(rcon, 2))
+    }
+    pub fn sub_word(&self, word: [u8; 4]) -> [u8; 4] {
+        word.map(|byte| self.sub_byte(byte))
+    }
+    pub fn next_key(
+        &self,
+        prev_key: [u8; 4],
+        current_key: [u8; 4],
+        key_length: usize,
+        expansion_counter: usize,
+    ) -> [u8; 4] {
+        get_next_key_with(
+            prev_key,
+            current_key,
+            key_length,
+            expansion_counter,
+            |word| self.sub_word(word),
+            |n| self.rcon(n),
+        )
+    }
+
+    pub fn key_expansion<T>(&self, key: &T) -> Vec<[u8; 4]>
+    where
+        T: AESKey,
+    {
+        let key_length = key.key_length();
+        let mut round_keys: Vec<[u8; 4]> = (0..key_length).map(|i| key.get_key_as_row(i)).collect();
+        for i in key_length..4 * (key.num_rounds() + 1) {
+            let next = self.next_key(round_keys[i - 1], round_keys[i - key_length], key_length, i);
+            round_keys.push(next);
+        }
+        round_keys
+    }
+
+    // Like `t_tables::generate_t_table` with the spec's S-box and field. The inverse
+    // coefficients (0e, 09, 0d, 0b) have degree < 8, so they invert MixColumns in any field.
+    pub fn t_table(&self, coefficients: [u8; 4], inverse: bool) -> [[u8; 4]; 256] {
+        let mut table = [[0u8; 4]; 256];
+        for (byte, entry) in table.iter_mut().enumerate() {
+            let s = if inverse {
+                self.inv_sub_byte(byte as u8)
+            } else {
+                self.sub_byte(byte as u8)
+            };
+            *entry = coefficients.map(|coefficient| self.field.mult(s, coefficient));
+        }
+        table
+    }
+
+    pub fn mix_columns(&self, mut state: State) -> State {
+        for c in 0..4 {
+            let column = state.get_row(c);
+            for r in 0..4 {
+                state[4 * c + r] = self.field.mult(2, column[r])
+                    ^ self.field.mult(3, column[(r + 1) % 4])
+                    ^ column[(r + 2) % 4]
+                    ^ column[(r + 3) % 4];
+            }
+        }
+        state
+    }
+    // MixColumns has order 4 over any field of characteristic 2, so M^-1 = M^3.
+    pub fn inv_mix_columns(&self, mut state: State) -> State {
+        for _ in 0..3 {
+            state = self.mix_columns(state);
+        }
+        state
+    }
+}
+
+// Tables generated from a spec for the SpeedEfficient path.
+struct SpecTables {
+    sbox: [u8; 256],
+    inverse_sbox: [u8; 256],
+    t: [[[u8; 4]; 256]; 4],
+    td: [[[u8; 4]; 256]; 4],
+}
+
+// What `encrypt_block` / `decrypt_block` swap out for a custom spec: SubBytes,
+// MixColumns and the key schedule. NoOptimization and MemoryEfficient compute every
+// byte through the field, SpeedEfficient reads tables generated from the spec.
+pub struct SpecRounds<'a> {
+    spec: &'a SBoxSpec,
+    tables: Option<Box<SpecTables>>,
+}
+
+impl<'a> SpecRounds<'a> {
+    pub fn new(spec: &'a SBoxSpec, optimization: AESOptimization) -> SpecRounds<'a> {
+        let tables = match optimization {
+            AESOptimization::NoOptimization | AESOptimization::MemoryEfficient => None,
+            AESOptimization::SpeedEfficient => Some(Box::new(SpecTables {
+                sbox: core::array::from_fn(|byte| spec.sub_byte(byte as u8)),
+                inverse_sbox: core::array::from_fn(|byte| spec.inv_sub_byte(byte as u8)),
+                t: [
+                    spec.t_table([0x02, 0x01, 0x01, 0x03], false),
+                    spec.t_table([0x03, 0x02, 0x01, 0x01], false),
+                    spec.t_table([0x01, 0x03, 0x02, 0x01], false),
+                    spec.t_table([0x01, 0x01, 0x03, 0x02], false),
+                ],
+                td: [
+                    spec.t_table([0x0e, 0x09, 0x0d, 0x0b], true),
+                    spec.t_table([0x0b, 0x0e, 0x09, 0x0d], true),
+                    spec.t_table([0x0d, 0x0b, 0x0e, 0x09], true),
+                    spec.t_table([0x09, 0x0d, 0x0b, 0x0e], true),
+                ],
+            })),
+            _ => panic!(
+                "Expected NoOptimization, MemoryEfficient or SpeedEfficient for a custom S-box but it was {:?}",
+                optimization
+            ),
+        };
+        SpecRounds { spec, tables }
+    }
+
+    pub fn sub_bytes(&self, mut state: State, inverse: bool) -> State {
+        for byte in state.iter_mut() {
+            *byte = match (&self.tables, inverse) {
+                (Some(tables), false) => tables.sbox[*byte as usize],
+                (Some(tables), true) => tables.inverse_sbox[*byte as usize],
+                (None, false) => self.spec.sub_byte(*byte),
+                (None, true) => self.spec.inv_sub_byte(*byte),
+            };
+        }
+        state
+    }
+    pub fn mix_columns(&self, state: State) -> State {
+        self.spec.mix_columns(state)
+    }
+    pub fn inv_mix_columns(&self, state: State) -> State {
+        self.spec.inv_mix_columns(state)
+    }
+    pub fn next_key(
+        &self,
+        prev_key: [u8; 4],
+        current_key: [u8; 4],
+        key_length: usize,
+        expansion_counter: usize,
+    ) -> [u8; 4] {
+        self.spec
+            .next_key(prev_key, current_key, key_length, expansion_counter)
+    }
+    // T0..T3, or TD0..TD3 with `inverse`, for `merged_op_with`.
+    pub fn t_tables(&self, inverse: bool) -> [&[[u8; 4]; 256]; 4] {
+        let tables = self
+            .tables
+            .as_ref()
+            .unwrap_or_else(|| panic!("Expected T-tables for SpeedEfficient but there were none"));
+        let t = if inverse { &tables.td } else { &tables.t };
+        [&t[0], &t[1], &t[2], &t[3]]
+    }
+
+    // Like `AESKey::key_expansion`, with `inverse` the middle round keys go through
+    // InvMixColumns for the equivalent inverse cipher.
+    pub fn key_expansion<T>(&self, key: &mut T, inverse: bool)
+    where
+        T: AESKey,
+    {
+        for (i, word) in self.spec.key_expansion(key).into_iter().enumerate() {
+            key.set_round_key(i, word);
+        }
+        if inverse {
+            for round in 1..key.num_rounds() {
+                let mixed = self.inv_mix_columns(State::from_words(key.get_round_subkey(round)));
+                for j in 0..4 {
+                    key.set_round_key(4 * round + j, mixed.get_row(j));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// Opt-in round-by-round view of the cipher for teaching and debugging. The
+// reference (NoOptimization) path reports every step, the other optimizations
+// only report the input, the state at the start of each round and the output.
+// Decryption follows the equivalent inverse cipher (FIPS-197 section 5.3.5) like
+// `decrypt_block`, its rows are the Equivalent Inverse Cipher rows of Appendix C.1.
+
+use crate::common::{key::AESKey, State};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RoundStep {
+    Input,
+    Start,
+    SubBytes,
+    ShiftRows,
+    MixColumns,
+    AddRoundKey,
+    KeySchedule,
+    Output,
+}
+
+impl RoundStep {
+    // Row labels used in FIPS-197 Appendix C, decryption rows carry an "i" prefix.
+    pub fn fips_label(&self, inverse: bool) -> String {
+        let label = match self {
+            RoundStep::Input => "input",
+            RoundStep::Start => "start",
+            RoundStep::SubBytes => "s_box",
+            RoundStep::ShiftRows => "s_row",
+            RoundStep::MixColumns => "m_col",
+            RoundStep::AddRoundKey => "k_add",
+            RoundStep::KeySchedule => "k_sch",
+            RoundStep::Output => "output",
+        };
+        if inverse {
+            format!("i{}", label)
+        } else {
+            label.to_string()
+        }
+    }
+}
+
+pub trait RoundObserver {
+    fn begin(&mut self, _inverse: bool) {}
+    // `round` counts from 0 to Nr in the order the rounds are executed, for
+    // KeySchedule the state holds the round key used in that round.
+    fn observe(&mut self, round: usize, step: RoundStep, state: &State);
+    // Called by the reference (NoOptimization) path right before `step` of `round` is
+    // applied, fault injection experiments may modify the state here.
+    fn inject_fault(&mut self, _round: usize, _step: RoundStep, _state: &mut State) {}
+    // The other optimizations merge the round steps and have nowhere to inject, they
+    // panic for observers that return true here.
+    fn injects_faults(&self) -> bool {
+        false
+    }
+}
+
+pub struct NoObserver;
+
+impl RoundObserver for NoObserver {
+    fn observe(&mut self, _round: usize, _step: RoundStep, _state: &State) {}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TraceEntry {
+    pub round: usize,
+    pub step: RoundStep,
+    pub state: State,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    pub inverse: bool,
+    pub entries: Vec<TraceEntry>,
+    pub key_schedule: Vec<[u8; 4]>,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    // Observers only see the round keys as they are used, exports also want the
+    // whole expanded key so it is recorded separately.
+    pub fn record_key_schedule<T>(&mut self, mut key: T)
+    where
+        T: AESKey,
+    {
+        key.key_expansion(false);
+        self.key_schedule = (0..4 * (key.num_rounds() + 1))
+            .map(|i| key.get_round_key(i))
+            .collect();
+    }
+
+    pub fn get(&self, round: usize, step: RoundStep) -> Option<&State> {
+        self.entries
+            .iter()
+            .find(|entry| entry.round == round && entry.step == step)
+            .map(|entry| &entry.state)
+    }
+
+    pub fn to_fips_string(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "round[{:>2}].{:<8}{}\n",
+                    entry.round,
+                    entry.step.fips_label(self.inverse),
+                    hex::encode(*entry.state)
+                )
+            })
+            .collect()
+    }
+}
+
+impl RoundObserver for Trace {
+    fn begin(&mut self, inverse: bool) {
+        self.inverse = inverse;
+        self.entries.clear();
+    }
+    fn observe(&mut self, round: usize, step: RoundStep, state: &State) {
+        self.entries.push(TraceEntry {
+            round,
+            step,
+            state: *state,
+        });
+    }
+}
@@ -0,0 +1,142 @@
+// JSON and standalone HTML renderings of a `Trace`. The HTML page has no external
+// resources so it can be handed out as a single file.
+
+use crate::common::{
+    trace::{RoundStep, Trace, TraceEntry},
+    State,
+};
+
+// Bytes that differ from the previous state of the trace. Key schedule rows are
+// not part of the data path, so they are compared against nothing.
+fn changed_bytes(previous: Option<&State>, state: &State) -> [bool; 16] {
+    let mut changed = [false; 16];
+    if let Some(previous) = previous {
+        for (i, flag) in changed.iter_mut().enumerate() {
+            *flag = previous[i] != state[i];
+        }
+    }
+    changed
+}
+
+fn entries_with_changes(trace: &Trace) -> Vec<(&TraceEntry, [bool; 16])> {
+    let mut previous: Option<&State> = None;
+    let mut result = Vec::new();
+    for entry in trace.entries.iter() {
+        if entry.step == RoundStep::KeySchedule {
+            result.push((entry, [false; 16]));
+            continue;
+        }
+        result.push((entry, changed_bytes(previous, &entry.state)));
+        previous = Some(&entry.state);
+    }
+    result
+}
+
+fn json_byte_list(bytes: &[u8]) -> String {
+    let items: Vec<String> = bytes.iter().map(|b| format!("\"{:02x}\"", b)).collect();
+    format!("[{}]", items.join(","))
+}
+
+pub fn trace_to_json(trace: &Trace) -> String {
+    let entries: Vec<String> = entries_with_changes(trace)
+        .iter()
+        .map(|(entry, changed)| {
+            let changed: Vec<String> = changed.iter().map(|c| c.to_string()).collect();
+            format!(
+                "{{\"round\":{},\"step\":\"{}\",\"state\":\"{}\",\"bytes\":{},\"changed\":[{}]}}",
+                entry.round,
+                entry.step.fips_label(trace.inverse),
+                hex::encode(*entry.state),
+                json_byte_list(&*entry.state),
+                changed.join(",")
+            )
+        })
+        .collect();
+    let words: Vec<String> = trace
+        .key_schedule
+        .iter()
+        .map(|word| format!("\"{}\"", hex::encode(word)))
+        .collect();
+
+    format!(
+        "{{\"inverse\":{},\"entries\":[{}],\"key_schedule\":[{}]}}",
+        trace.inverse,
+        entries.join(","),
+        words.join(",")
+    )
+}
+
+// The state is stored column by column (byte 4c + r), the grid is drawn row by row.
+fn state_grid(state: &State, changed: &[bool; 16]) -> String {
+    let mut html = String::from("<table class=\"state\">");
+    for r in 0..4 {
+        html.push_str("<tr>");
+        for c in 0..4 {
+            let class = if changed[4 * c + r] {
+                " class=\"changed\""
+            } else {
+                ""
+            };
+            html.push_str(&format!("<td{}>{:02x}</td>", class, state[4 * c + r]));
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+    html
+}
+
+pub fn trace_to_html(trace: &Trace) -> String {
+    let title = if trace.inverse {
+        "AES decryption trace"
+    } else {
+        "AES encryption trace"
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; }}\n\
+         .round {{ display: flex; flex-wrap: wrap; align-items: flex-start; margin-bottom: 1em; }}\n\
+         .step {{ margin-right: 1em; text-align: center; }}\n\
+         table.state td {{ font-family: monospace; border: 1px solid #999; padding: 2px 4px; }}\n\
+         table.state td.changed {{ background: #ffd54f; }}\n\
+         table.words td {{ font-family: monospace; padding: 0 8px; }}\n\
+         </style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, title
+    );
+
+    let mut current_round = None;
+    for (entry, changed) in entries_with_changes(trace) {
+        if current_round != Some(entry.round) {
+            if current_round.is_some() {
+                html.push_str("</div>\n");
+            }
+            html.push_str(&format!(
+                "<h2>round[{:>2}]</h2>\n<div class=\"round\">\n",
+                entry.round
+            ));
+            current_round = Some(entry.round);
+        }
+        html.push_str(&format!(
+            "<div class=\"step\">{}<br>{}</div>\n",
+            entry.step.fips_label(trace.inverse),
+            state_grid(&entry.state, &changed)
+        ));
+    }
+    if current_round.is_some() {
+        html.push_str("</div>\n");
+    }
+
+    if !trace.key_schedule.is_empty() {
+        html.push_str("<h2>Key schedule</h2>\n<table class=\"words\">\n");
+        for (i, word) in trace.key_schedule.iter().enumerate() {
+            html.push_str(&format!(
+                "<tr><td>w[{}]</td><td>{}</td></tr>\n",
+                i,
+                hex::encode(word)
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
@@ -0,0 +1,381 @@
+// Vector-permute AES after Hamburg, "Accelerating AES with Vector Permute
+// Instructions" (CHES 2009). Bytes are mapped into GF(2^4)[t]/(t^2 + a*t + a),
+// x = i*t + k, and the inverse is assembled from pshufb lookups of 16-entry GF(2^4)
+// tables, so every memory access is a register shuffle independent of the data.
+// The tables are derived at compile time from the GF(2^4) subfield of the AES field.
+
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{
+    __m128i, _mm_add_epi8, _mm_and_si128, _mm_cmplt_epi8, _mm_loadu_si128, _mm_set1_epi8,
+    _mm_setzero_si128, _mm_shuffle_epi8, _mm_srli_epi16, _mm_storeu_si128, _mm_xor_si128,
+};
+
+use crate::common::{
+    bitslice::{
+        bitsliced_inv_sub_bytes, bitsliced_op, bitsliced_op_inverse, bitsliced_sub_bytes,
+        bitsliced_sub_word,
+    },
+    cipher_operations::mult,
+    key::AESKey,
+    math::pow,
+    State,
+};
+
+// pshufb returns 0 for indices with the high bit set, which stands in for 1/0.
+const INFINITY: u8 = 0x80;
+
+// 0x03 generates GF(2^8)*, so 0x03^17 has order 15 and generates the GF(2^4) subfield.
+const SUBFIELD_GENERATOR: u8 = pow(0x03, 17);
+
+const fn nibble_to_field(nibble: u8) -> u8 {
+    let mut result = 0;
+    let mut b = 0;
+    while b < 4 {
+        if (nibble >> b) & 1 == 1 {
+            result ^= pow(SUBFIELD_GENERATOR, b);
+        }
+        b += 1;
+    }
+    result
+}
+
+const fn field_to_nibble(element: u8) -> u8 {
+    let mut nibble = 0;
+    while nibble < 16 {
+        if nibble_to_field(nibble) == element {
+            return nibble;
+        }
+        nibble += 1;
+    }
+    panic!("element is not in the GF(2^4) subfield");
+}
+
+const fn nibble_mul(x: u8, y: u8) -> u8 {
+    field_to_nibble(mult(nibble_to_field(x), nibble_to_field(y)))
+}
+
+const fn nibble_inverse(x: u8) -> u8 {
+    field_to_nibble(pow(nibble_to_field(x), 14))
+}
+
+// Finds t outside the subfield whose minimal polynomial over GF(2^4) is t^2 + a*t + a.
+const fn find_tower_root() -> (u8, u8) {
+    let mut t = 2;
+    while t < 256 {
+        let conjugate = pow(t as u8, 16);
+        let trace = t as u8 ^ conjugate;
+        let norm = mult(t as u8, conjugate);
+        if conjugate != t as u8 && trace == norm {
+            return (t as u8, field_to_nibble(trace));
+        }
+        t += 1;
+    }
+    panic!("no tower field root found");
+}
+
+const TOWER_ROOT: (u8, u8) = find_tower_root();
+
+const fn tower_to_standard(byte: u8) -> u8 {
+    mult(nibble_to_field(byte >> 4), TOWER_ROOT.0) ^ nibble_to_field(byte & 0x0f)
+}
+
+const fn standard_to_tower(byte: u8) -> u8 {
+    let mut tower = 0;
+    while tower < 255 {
+        if tower_to_standard(tower) == byte {
+            return tower;
+        }
+        tower += 1;
+    }
+    tower
+}
+
+const fn affine(byte: u8) -> u8 {
+    byte ^ byte.rotate_left(1) ^ byte.rotate_left(2) ^ byte.rotate_left(3) ^ byte.rotate_left(4)
+}
+
+const fn inv_affine(byte: u8) -> u8 {
+    byte.rotate_left(1) ^ byte.rotate_left(3) ^ byte.rotate_left(6)
+}
+
+const fn input_table(high: bool, inverse: bool) -> [u8; 16] {
+    let mut table = [0u8; 16];
+    let mut n = 0;
+    while n < 16 {
+        let byte = if high { (n as u8) << 4 } else { n as u8 };
+        table[n] = if inverse {
+            let constant = if high { 0 } else { standard_to_tower(0x05) };
+            standard_to_tower(inv_affine(byte)) ^ constant
+        } else {
+            standard_to_tower(byte)
+        };
+        n += 1;
+    }
+    table
+}
+
+const fn inverse_table(numerator: u8) -> [u8; 16] {
+    let mut table = [INFINITY; 16];
+    let mut n = 1;
+    while n < 16 {
+        table[n] = nibble_mul(numerator, nibble_inverse(n as u8));
+        n += 1;
+    }
+    table
+}
+
+// With u = 1/io and w = 1/jo the inverse of i*t + k is
+// ((a^-1 + a^-2) * u + a^-2 * w) * t + u, the tables add the contribution of u and w.
+const fn output_table(from_io: bool, inverse: bool) -> [u8; 16] {
+    let a_inverse = nibble_inverse(TOWER_ROOT.1);
+    let a_inverse_squared = nibble_mul(a_inverse, a_inverse);
+
+    let mut table = [0u8; 16];
+    let mut n = 1;
+    while n < 16 {
+        let v = nibble_inverse(n as u8);
+        let tower = if from_io {
+            (nibble_mul(a_inverse ^ a_inverse_squared, v) << 4) | v
+        } else {
+            nibble_mul(a_inverse_squared, v) << 4
+        };
+        let standard = tower_to_standard(tower);
+        table[n] = if inverse { standard } else { affine(standard) };
+        n += 1;
+    }
+    table
+}
+
+const INPUT_LOW: [u8; 16] = input_table(false, false);
+const INPUT_HIGH: [u8; 16] = input_table(true, false);
+const INV_INPUT_LOW: [u8; 16] = input_table(false, true);
+const INV_INPUT_HIGH: [u8; 16] = input_table(true, true);
+const INVERSE: [u8; 16] = inverse_table(1);
+const A_OVER: [u8; 16] = inverse_table(TOWER_ROOT.1);
+const OUTPUT_IO: [u8; 16] = output_table(true, false);
+const OUTPUT_JO: [u8; 16] = output_table(false, false);
+const INV_OUTPUT_IO: [u8; 16] = output_table(true, true);
+const INV_OUTPUT_JO: [u8; 16] = output_table(false, true);
+
+const fn shift_rows_table(inverse: bool) -> [u8; 16] {
+    let mut table = [0u8; 16];
+    let mut c = 0;
+    while c < 4 {
+        let mut r = 0;
+        while r < 4 {
+            let shifted = (4 * ((c + r) % 4) + r) as u8;
+            if inverse {
+                table[shifted as usize] = (4 * c + r) as u8;
+            } else {
+                table[4 * c + r] = shifted;
+            }
+            r += 1;
+        }
+        c += 1;
+    }
+    table
+}
+
+const fn rotate_columns_table(n: usize) -> [u8; 16] {
+    let mut table = [0u8; 16];
+    let mut i = 0;
+    while i < 16 {
+        table[i] = (4 * (i / 4) + (i % 4 + n) % 4) as u8;
+        i += 1;
+    }
+    table
+}
+
+const SHIFT_ROWS: [u8; 16] = shift_rows_table(false);
+const INV_SHIFT_ROWS: [u8; 16] = shift_rows_table(true);
+const ROTATE_1: [u8; 16] = rotate_columns_table(1);
+const ROTATE_2: [u8; 16] = rotate_columns_table(2);
+const ROTATE_3: [u8; 16] = rotate_columns_table(3);
+
+pub fn is_vector_permute_supported() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("ssse3")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn load(bytes: &[u8; 16]) -> __m128i {
+    _mm_loadu_si128(bytes.as_ptr() as *const __m128i)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn store(value: __m128i) -> State {
+    let mut state = State::get_empty_state();
+    _mm_storeu_si128(state.as_mut_ptr() as *mut __m128i, value);
+    state
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn lookup(table: &[u8; 16], indices: __m128i) -> __m128i {
+    _mm_shuffle_epi8(load(table), indices)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn sub_bytes_vector(x: __m128i, inverse: bool) -> __m128i {
+    let (input_low, input_high, output_io, output_jo) = if inverse {
+        (
+            &INV_INPUT_LOW,
+            &INV_INPUT_HIGH,
+            &INV_OUTPUT_IO,
+            &INV_OUTPUT_JO,
+        )
+    } else {
+        (&INPUT_LOW, &INPUT_HIGH, &OUTPUT_IO, &OUTPUT_JO)
+    };
+    let low_nibbles = _mm_set1_epi8(0x0f);
+
+    let tower = _mm_xor_si128(
+        lookup(input_low, _mm_and_si128(x, low_nibbles)),
+        lookup(input_high, _mm_and_si128(_mm_srli_epi16(x, 4), low_nibbles)),
+    );
+    let k = _mm_and_si128(tower, low_nibbles);
+    let i = _mm_and_si128(_mm_srli_epi16(tower, 4), low_nibbles);
+    let j = _mm_xor_si128(i, k);
+
+    let a_over_k = lookup(&A_OVER, k);
+    let iak = _mm_xor_si128(lookup(&INVERSE, i), a_over_k);
+    let jak = _mm_xor_si128(lookup(&INVERSE, j), a_over_k);
+    let io = _mm_xor_si128(j, lookup(&INVERSE, iak));
+    let jo = _mm_xor_si128(i, lookup(&INVERSE, jak));
+
+    let result = _mm_xor_si128(lookup(output_io, io), lookup(output_jo, jo));
+    if inverse {
+        result
+    } else {
+        _mm_xor_si128(result, _mm_set1_epi8(0x63))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn xtime_vector(x: __m128i) -> __m128i {
+    let high_bits = _mm_cmplt_epi8(x, _mm_setzero_si128());
+    _mm_xor_si128(
+        _mm_add_epi8(x, x),
+        _mm_and_si128(high_bits, _mm_set1_epi8(0x1b)),
+    )
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn mix_columns_vector(x: __m128i) -> __m128i {
+    let r1 = _mm_shuffle_epi8(x, load(&ROTATE_1));
+    let r2 = _mm_shuffle_epi8(x, load(&ROTATE_2));
+    let r3 = _mm_shuffle_epi8(x, load(&ROTATE_3));
+
+    let doubled = xtime_vector(_mm_xor_si128(x, r1));
+    _mm_xor_si128(_mm_xor_si128(doubled, r1), _mm_xor_si128(r2, r3))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn inv_mix_columns_vector(x: __m128i) -> __m128i {
+    let folded = _mm_xor_si128(x, _mm_shuffle_epi8(x, load(&ROTATE_2)));
+    mix_columns_vector(_mm_xor_si128(x, xtime_vector(xtime_vector(folded))))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn round_vector(state: &State, round_key: &State, inverse: bool) -> State {
+    let x = load(state);
+    let x = if inverse {
+        let x = sub_bytes_vector(_mm_shuffle_epi8(x, load(&INV_SHIFT_ROWS)), true);
+        inv_mix_columns_vector(_mm_xor_si128(x, load(round_key)))
+    } else {
+        let x = _mm_shuffle_epi8(sub_bytes_vector(x, false), load(&SHIFT_ROWS));
+        _mm_xor_si128(mix_columns_vector(x), load(round_key))
+    };
+    store(x)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn sub_bytes_state(state: &State, inverse: bool) -> State {
+    store(sub_bytes_vector(load(state), inverse))
+}
+
+// Whether `vector_permute_key_expansion` found SSSE3, the round functions fall back
+// to the bitsliced implementation when it is not set.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VectorPermuteSupport {
+    ssse3: bool,
+}
+
+fn vector_permute_sub_word(word: [u8; 4], support: VectorPermuteSupport) -> [u8; 4] {
+    #[cfg(target_arch = "x86_64")]
+    if support.ssse3 {
+        let state = unsafe { sub_bytes_state(&State::from_words([word; 4]), false) };
+        return state.get_row(0);
+    }
+    bitsliced_sub_word(word)
+}
+
+pub fn vector_permute_key_expansion<T>(key: &mut T) -> VectorPermuteSupport
+where
+    T: AESKey,
+{
+    let support = VectorPermuteSupport {
+        ssse3: is_vector_permute_supported(),
+    };
+    key.key_expansion_with(|word| vector_permute_sub_word(word, support));
+    support
+}
+
+pub fn vector_permute_sub_bytes(state: State, support: VectorPermuteSupport) -> State {
+    #[cfg(target_arch = "x86_64")]
+    if support.ssse3 {
+        return unsafe { sub_bytes_state(&state, false) };
+    }
+    bitsliced_sub_bytes(state)
+}
+
+pub fn vector_permute_inv_sub_bytes(state: State, support: VectorPermuteSupport) -> State {
+    #[cfg(target_arch = "x86_64")]
+    if support.ssse3 {
+        return unsafe { sub_bytes_state(&state, true) };
+    }
+    bitsliced_inv_sub_bytes(state)
+}
+
+pub fn vector_permute_op<T>(state: State, i: usize, key: &T, support: VectorPermuteSupport) -> State
+where
+    T: AESKey,
+{
+    #[cfg(target_arch = "x86_64")]
+    if support.ssse3 {
+        let round_key = State::from_words(key.get_round_subkey(i));
+        return unsafe { round_vector(&state, &round_key, false) };
+    }
+    bitsliced_op(state, i, key)
+}
+
+pub fn vector_permute_op_inverse<T>(
+    state: State,
+    i: usize,
+    key: &T,
+    support: VectorPermuteSupport,
+) -> State
+where
+    T: AESKey,
+{
+    #[cfg(target_arch = "x86_64")]
+    if support.ssse3 {
+        let round_key = State::from_words(key.get_round_subkey(i));
+        return unsafe { round_vector(&state, &round_key, true) };
+    }
+    bitsliced_op_inverse(state, i, key)
+}
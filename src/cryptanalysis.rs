@@ -0,0 +1,15 @@
+// Tools for teaching attacks on AES. Nothing in here is meant to protect data,
+// production code should use `encrypt_block` / `decrypt_block` from the crate root.
+pub mod algebraic;
+pub mod cpa;
+pub mod dfa;
+pub mod inverse_key_schedule;
+pub mod key_scanner;
+pub mod masking;
+pub mod power_trace;
+pub mod reduced_round;
+pub mod related_key;
+pub mod sbox_analysis;
+pub mod small_scale;
+pub mod square;
+pub mod truncated;
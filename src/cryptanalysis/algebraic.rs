@@ -0,0 +1,751 @@
+// Algebraic descriptions of small-scale AES SR(n, r, c, e), and with it of
+// reduced-round AES-128, for Groebner basis and SAT solvers. The model is built from
+// the cipher's own S-box, ShiftRows, MixColumns and round constants, known
+// plaintext / ciphertext pairs are added as constraints.
+//
+// - Over GF(2^e) every S-box gives x^2 w = x, x w^2 = w (so w = x^-1 and 0 for
+//   x = 0) and y = L(w) + S(0), with the affine map L as a linearized polynomial.
+// - Over GF(2) every S-box gives the quadratic relations between its input and
+//   output bits that hold for all inputs (21 for e = 4, 39 for e = 8), which
+//   describe it exactly; the linear layers are written bit by bit.
+// - The CNF encodes the GF(2) system, every product of two variables and every
+//   long XOR gets an auxiliary variable (Bard, Courtois and Jefferson).
+
+use std::collections::HashMap;
+
+use crate::cryptanalysis::small_scale::SmallScaleAES;
+
+// XORs with more variables are cut, an XOR of k variables needs 2^(k - 1) clauses.
+const XOR_CUT: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Constraint {
+    // The sum of coefficient * variable plus the constant is 0.
+    Linear {
+        terms: Vec<(u8, usize)>,
+        constant: u8,
+    },
+    // output = S(input), `inverse` = input^-1 only appears over GF(2^e).
+    SBox {
+        input: usize,
+        inverse: usize,
+        output: usize,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct AlgebraicModel {
+    cipher: SmallScaleAES,
+    pub variables: Vec<String>,
+    pub constraints: Vec<Constraint>,
+    pub key_variables: Vec<usize>,
+    // c_i is the coefficient of w^(2^i) in L(w).
+    linearized_affine: Vec<u8>,
+}
+
+// Gaussian elimination over GF(2^e) on the basis 1, a, .., a^(e - 1), with
+// L(w) = S(w^-1) + S(0).
+fn linearized_affine(cipher: &SmallScaleAES) -> Vec<u8> {
+    let e = cipher.word_size();
+    let mut matrix: Vec<Vec<u8>> = (0..e)
+        .map(|k| {
+            let w = 1u8 << k;
+            let mut row: Vec<u8> = (0..e).map(|i| frobenius(cipher, w, i)).collect();
+            row.push(cipher.sbox(cipher.inverse(w)) ^ cipher.sbox(0));
+            row
+        })
+        .collect();
+
+    for column in 0..e {
+        let pivot = (column..e)
+            .find(|row| matrix[*row][column] != 0)
+            .unwrap_or_else(|| panic!("The affine map of the S-box is not GF(2)-linear"));
+        matrix.swap(column, pivot);
+        let scale = cipher.inverse(matrix[column][column]);
+        matrix[column] = matrix[column]
+            .iter()
+            .map(|value| cipher.mult(*value, scale))
+            .collect();
+        let pivot_row = matrix[column].clone();
+        for (index, row) in matrix.iter_mut().enumerate() {
+            let factor = row[column];
+            if index != column && factor != 0 {
+                for (value, pivot_value) in row.iter_mut().zip(pivot_row.iter()) {
+                    *value ^= cipher.mult(factor, *pivot_value);
+                }
+            }
+        }
+    }
+    matrix.iter().map(|row| row[e]).collect()
+}
+
+// w^(2^i)
+fn frobenius(cipher: &SmallScaleAES, w: u8, i: usize) -> u8 {
+    (0..i).fold(w, |power, _| cipher.mult(power, power))
+}
+
+// Quadratic relations between the input bits (0..e) and output bits (e..2e) of the
+// S-box, the kernel of the matrix of all monomials of degree <= 2 evaluated on all inputs.
+pub fn sbox_relations(cipher: &SmallScaleAES) -> Vec<Vec<Vec<usize>>> {
+    let e = cipher.word_size();
+    let mut monomials: Vec<Vec<usize>> = vec![vec![]];
+    monomials.extend((0..2 * e).map(|i| vec![i]));
+    for i in 0..2 * e {
+        monomials.extend((i + 1..2 * e).map(|j| vec![i, j]));
+    }
+    let mut rows: Vec<Vec<bool>> = (0..1usize << e)
+        .map(|x| {
+            let bits = x | (cipher.sbox(x as u8) as usize) << e;
+            monomials
+                .iter()
+                .map(|monomial| monomial.iter().all(|i| (bits >> i) & 1 == 1))
+                .collect()
+        })
+        .collect();
+
+    // Reduced row echelon form, `pivots[row]` is the pivot column of `row`.
+    let mut pivots = Vec::new();
+    for column in 0..monomials.len() {
+        let rank = pivots.len();
+        let Some(pivot) = (rank..rows.len()).find(|row| rows[*row][column]) else {
+            continue;
+        };
+        rows.swap(rank, pivot);
+        let pivot_row = rows[rank].clone();
+        for (index, row) in rows.iter_mut().enumerate() {
+            if index != rank && row[column] {
+                row.iter_mut()
+                    .zip(pivot_row.iter())
+                    .for_each(|(value, pivot_value)| *value ^= pivot_value);
+            }
+        }
+        pivots.push(column);
+    }
+
+    (0..monomials.len())
+        .filter(|column| !pivots.contains(column))
+        .map(|free| {
+            let mut relation = vec![monomials[free].clone()];
+            for (row, pivot) in pivots.iter().enumerate() {
+                if rows[row][free] {
+                    relation.push(monomials[*pivot].clone());
+                }
+            }
+            relation
+        })
+        .collect()
+}
+
+impl AlgebraicModel {
+    // `pairs` holds plaintext / ciphertext words, every pair gets its own state variables.
+    pub fn new(cipher: SmallScaleAES, pairs: &[(Vec<u8>, Vec<u8>)]) -> AlgebraicModel {
+        let mut model = AlgebraicModel {
+            cipher,
+            variables: Vec::new(),
+            constraints: Vec::new(),
+            key_variables: Vec::new(),
+            linearized_affine: linearized_affine(&cipher),
+        };
+        let (rows, columns, rounds) = (cipher.rows(), cipher.columns(), cipher.num_rounds());
+        let n = cipher.block_length();
+
+        let master_key: Vec<usize> = (0..n)
+            .map(|i| model.variable(format!("k0_{}", i)))
+            .collect();
+        model.key_variables = master_key.clone();
+        let mut round_keys = vec![master_key];
+        for round in 1..=rounds {
+            let previous = round_keys[round - 1].clone();
+            let substituted: Vec<usize> = (0..rows)
+                .map(|row| {
+                    model.sbox(
+                        previous[cipher.at((row + 1) % rows, columns - 1)],
+                        format!("v{}_{}", round, row),
+                        format!("s{}_{}", round, row),
+                    )
+                })
+                .collect();
+            let mut round_key = vec![0; n];
+            for column in 0..columns {
+                for row in 0..rows {
+                    let index = cipher.at(row, column);
+                    let word = model.variable(format!("k{}_{}", round, index));
+                    let mut terms = vec![(1, word), (1, previous[index])];
+                    let mut constant = 0;
+                    if column == 0 {
+                        terms.push((1, substituted[row]));
+                        if row == 0 {
+                            constant = cipher.round_constant(round);
+                        }
+                    } else {
+                        terms.push((1, round_key[cipher.at(row, column - 1)]));
+                    }
+                    model
+                        .constraints
+                        .push(Constraint::Linear { terms, constant });
+                    round_key[index] = word;
+                }
+            }
+            round_keys.push(round_key);
+        }
+
+        // ShiftRows as a permutation and MixColumns as a matrix, taken from the cipher.
+        let mut permutation: Vec<u8> = (0..n as u8).collect();
+        cipher.shift_rows(&mut permutation, false);
+        // matrix[i][j] is the coefficient of input row j in output row i.
+        let matrix: Vec<Vec<u8>> = (0..rows)
+            .map(|i| {
+                (0..rows)
+                    .map(|j| {
+                        let mut unit = vec![0u8; n];
+                        unit[cipher.at(j, 0)] = 1;
+                        cipher.mix_columns(&mut unit);
+                        unit[cipher.at(i, 0)]
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for (pair, (plaintext, ciphertext)) in pairs.iter().enumerate() {
+            cipher.check_length(plaintext);
+            cipher.check_length(ciphertext);
+            let mut state: Vec<usize> = (0..n)
+                .map(|i| {
+                    let x = model.variable(format!("x{}_1_{}", pair, i));
+                    model.constraints.push(Constraint::Linear {
+                        terms: vec![(1, x), (1, round_keys[0][i])],
+                        constant: plaintext[i],
+                    });
+                    x
+                })
+                .collect();
+
+            for (round, round_key) in round_keys.iter().enumerate().skip(1) {
+                let substituted: Vec<usize> = state
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| {
+                        model.sbox(
+                            *x,
+                            format!("w{}_{}_{}", pair, round, i),
+                            format!("y{}_{}_{}", pair, round, i),
+                        )
+                    })
+                    .collect();
+                let shifted: Vec<usize> = permutation
+                    .iter()
+                    .map(|source| substituted[*source as usize])
+                    .collect();
+                let mix = round < rounds || !cipher.star();
+
+                let mut next = vec![0; n];
+                for column in 0..columns {
+                    for (row, coefficients) in matrix.iter().enumerate() {
+                        let index = cipher.at(row, column);
+                        let mut terms: Vec<(u8, usize)> = if mix {
+                            coefficients
+                                .iter()
+                                .enumerate()
+                                .map(|(j, coefficient)| {
+                                    (*coefficient, shifted[cipher.at(j, column)])
+                                })
+                                .filter(|(coefficient, _)| *coefficient != 0)
+                                .collect()
+                        } else {
+                            vec![(1, shifted[index])]
+                        };
+                        terms.push((1, round_key[index]));
+                        if round == rounds {
+                            model.constraints.push(Constraint::Linear {
+                                terms,
+                                constant: ciphertext[index],
+                            });
+                        } else {
+                            let x = model.variable(format!("x{}_{}_{}", pair, round + 1, index));
+                            terms.insert(0, (1, x));
+                            model
+                                .constraints
+                                .push(Constraint::Linear { terms, constant: 0 });
+                            next[index] = x;
+                        }
+                    }
+                }
+                state = next;
+            }
+        }
+        model
+    }
+
+    // Reduced-round AES-128, without MixColumns in the last round unless `final_mix_columns`.
+    pub fn reduced_round_aes(
+        rounds: usize,
+        final_mix_columns: bool,
+        pairs: &[([u8; 16], [u8; 16])],
+    ) -> AlgebraicModel {
+        let pairs: Vec<(Vec<u8>, Vec<u8>)> = pairs
+            .iter()
+            .map(|(plaintext, ciphertext)| (plaintext.to_vec(), ciphertext.to_vec()))
+            .collect();
+        AlgebraicModel::new(
+            SmallScaleAES::new(rounds, 4, 4, 8, !final_mix_columns),
+            &pairs,
+        )
+    }
+
+    pub fn cipher(&self) -> SmallScaleAES {
+        self.cipher
+    }
+
+    fn variable(&mut self, name: String) -> usize {
+        self.variables.push(name);
+        self.variables.len() - 1
+    }
+
+    fn sbox(&mut self, input: usize, inverse: String, output: String) -> usize {
+        let inverse = self.variable(inverse);
+        let output = self.variable(output);
+        self.constraints.push(Constraint::SBox {
+            input,
+            inverse,
+            output,
+        });
+        output
+    }
+
+    // The value of every variable for `key`, derived constraint by constraint.
+    // Constraints without an unknown left (the ciphertexts) are not checked here.
+    pub fn assignment(&self, key: &[u8]) -> Vec<u8> {
+        self.cipher.check_length(key);
+        let mut values: Vec<Option<u8>> = vec![None; self.variables.len()];
+        for (variable, word) in self.key_variables.iter().zip(key.iter()) {
+            values[*variable] = Some(*word);
+        }
+        for constraint in self.constraints.iter() {
+            match constraint {
+                Constraint::Linear { terms, constant } => {
+                    let unknown: Vec<usize> = terms
+                        .iter()
+                        .filter(|(_, variable)| values[*variable].is_none())
+                        .map(|(_, variable)| *variable)
+                        .collect();
+                    if unknown.len() == 1 {
+                        let sum = terms
+                            .iter()
+                            .filter_map(|(coefficient, variable)| {
+                                values[*variable].map(|value| self.cipher.mult(*coefficient, value))
+                            })
+                            .fold(*constant, |sum, value| sum ^ value);
+                        values[unknown[0]] = Some(sum);
+                    }
+                }
+                Constraint::SBox {
+                    input,
+                    inverse,
+                    output,
+                } => {
+                    let x = values[*input]
+                        .unwrap_or_else(|| panic!("{} is not known", self.variables[*input]));
+                    values[*inverse] = Some(self.cipher.inverse(x));
+                    values[*output] = Some(self.cipher.sbox(x));
+                }
+            }
+        }
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| value.unwrap_or_else(|| panic!("{} is not known", self.variables[i])))
+            .collect()
+    }
+
+    // Evaluates the GF(2^e) equations as they are exported.
+    pub fn is_satisfied_by(&self, values: &[u8]) -> bool {
+        let mult = |a: u8, b: u8| self.cipher.mult(a, b);
+        self.constraints.iter().all(|constraint| match constraint {
+            Constraint::Linear { terms, constant } => {
+                terms
+                    .iter()
+                    .fold(*constant, |sum, (coefficient, variable)| {
+                        sum ^ mult(*coefficient, values[*variable])
+                    })
+                    == 0
+            }
+            Constraint::SBox {
+                input,
+                inverse,
+                output,
+            } => {
+                let (x, w, y) = (values[*input], values[*inverse], values[*output]);
+                let affine = self
+                    .linearized_affine
+                    .iter()
+                    .enumerate()
+                    .fold(self.cipher.sbox(0), |sum, (i, coefficient)| {
+                        sum ^ mult(*coefficient, frobenius(&self.cipher, w, i))
+                    });
+                mult(mult(x, x), w) == x && mult(x, mult(w, w)) == w && y == affine
+            }
+        })
+    }
+
+    fn gf2e_term(coefficient: u8, name: &str, exponent: usize) -> String {
+        let power = if exponent == 1 {
+            name.to_string()
+        } else {
+            format!("{}^{}", name, exponent)
+        };
+        if coefficient == 1 {
+            power
+        } else {
+            format!("{:#x}*{}", coefficient, power)
+        }
+    }
+
+    // One polynomial per line, every polynomial is 0. Field elements are written as
+    // integers in hex, bit i being the coefficient of a^i.
+    pub fn to_gf2e_system(&self) -> String {
+        let e = self.cipher.word_size();
+        let modulus = if e == 4 {
+            "a^4 + a + 1"
+        } else {
+            "a^8 + a^4 + a^3 + a + 1"
+        };
+        let mut system = format!(
+            "# SR({}, {}, {}, {}) over GF(2^{}) = GF(2)[a] / ({})\n# variables: {}\n",
+            self.cipher.num_rounds(),
+            self.cipher.rows(),
+            self.cipher.columns(),
+            e,
+            e,
+            modulus,
+            self.variables.join(", ")
+        );
+        for constraint in self.constraints.iter() {
+            match constraint {
+                Constraint::Linear { terms, constant } => {
+                    let mut polynomial: Vec<String> = terms
+                        .iter()
+                        .map(|(coefficient, variable)| {
+                            AlgebraicModel::gf2e_term(*coefficient, &self.variables[*variable], 1)
+                        })
+                        .collect();
+                    if *constant != 0 {
+                        polynomial.push(format!("{:#x}", constant));
+                    }
+                    system.push_str(&polynomial.join(" + "));
+                    system.push('\n');
+                }
+                Constraint::SBox {
+                    input,
+                    inverse,
+                    output,
+                } => {
+                    let (x, w, y) = (
+                        &self.variables[*input],
+                        &self.variables[*inverse],
+                        &self.variables[*output],
+                    );
+                    system.push_str(&format!("{}^2*{} + {}\n", x, w, x));
+                    system.push_str(&format!("{}*{}^2 + {}\n", x, w, w));
+                    let mut polynomial = vec![y.to_string()];
+                    for (i, coefficient) in self.linearized_affine.iter().enumerate() {
+                        if *coefficient != 0 {
+                            polynomial.push(AlgebraicModel::gf2e_term(*coefficient, w, 1 << i));
+                        }
+                    }
+                    polynomial.push(format!("{:#x}", self.cipher.sbox(0)));
+                    system.push_str(&polynomial.join(" + "));
+                    system.push('\n');
+                }
+            }
+        }
+        system
+    }
+
+    // The first GF(2) variable of every word, the S-box inverses have none.
+    fn bit_offsets(&self) -> Vec<Option<usize>> {
+        let mut inverses = vec![false; self.variables.len()];
+        for constraint in self.constraints.iter() {
+            if let Constraint::SBox { inverse, .. } = constraint {
+                inverses[*inverse] = true;
+            }
+        }
+        let e = self.cipher.word_size();
+        let mut next = 0;
+        inverses
+            .iter()
+            .map(|inverse| {
+                if *inverse {
+                    return None;
+                }
+                next += e;
+                Some(next - e)
+            })
+            .collect()
+    }
+
+    pub fn gf2_system(&self) -> Gf2System {
+        let e = self.cipher.word_size();
+        let offsets = self.bit_offsets();
+        let mut variables = Vec::new();
+        for (name, offset) in self.variables.iter().zip(offsets.iter()) {
+            if offset.is_some() {
+                variables.extend((0..e).map(|bit| format!("{}_{}", name, bit)));
+            }
+        }
+        let bit = |variable: usize, bit: usize| {
+            offsets[variable]
+                .unwrap_or_else(|| panic!("{} has no GF(2) variables", self.variables[variable]))
+                + bit
+        };
+
+        let relations = sbox_relations(&self.cipher);
+        let mut equations = Vec::new();
+        for constraint in self.constraints.iter() {
+            match constraint {
+                Constraint::Linear { terms, constant } => {
+                    for i in 0..e {
+                        let mut equation = Vec::new();
+                        for (coefficient, variable) in terms.iter() {
+                            for j in 0..e {
+                                if (self.cipher.mult(*coefficient, 1 << j) >> i) & 1 == 1 {
+                                    equation.push(vec![bit(*variable, j)]);
+                                }
+                            }
+                        }
+                        if (constant >> i) & 1 == 1 {
+                            equation.push(vec![]);
+                        }
+                        equations.push(normalize(equation));
+                    }
+                }
+                Constraint::SBox { input, output, .. } => {
+                    let local = |i: usize| {
+                        if i < e {
+                            bit(*input, i)
+                        } else {
+                            bit(*output, i - e)
+                        }
+                    };
+                    for relation in relations.iter() {
+                        let equation = relation
+                            .iter()
+                            .map(|monomial| monomial.iter().map(|i| local(*i)).collect())
+                            .collect();
+                        equations.push(normalize(equation));
+                    }
+                }
+            }
+        }
+        Gf2System {
+            variables,
+            equations,
+        }
+    }
+
+    // Bit j of a word is the coefficient of a^j.
+    pub fn bit_assignment(&self, values: &[u8]) -> Vec<bool> {
+        let e = self.cipher.word_size();
+        self.bit_offsets()
+            .iter()
+            .zip(values.iter())
+            .filter(|(offset, _)| offset.is_some())
+            .flat_map(|(_, value)| (0..e).map(move |bit| (value >> bit) & 1 == 1))
+            .collect()
+    }
+}
+
+// Sorts the variables of every monomial and the monomials, pairs cancel.
+fn normalize(equation: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+    let mut monomials: Vec<Vec<usize>> = equation
+        .into_iter()
+        .map(|mut monomial| {
+            monomial.sort();
+            monomial
+        })
+        .collect();
+    monomials.sort();
+    let mut normalized: Vec<Vec<usize>> = Vec::new();
+    for monomial in monomials {
+        if normalized.last() == Some(&monomial) {
+            normalized.pop();
+        } else {
+            normalized.push(monomial);
+        }
+    }
+    normalized
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gf2System {
+    pub variables: Vec<String>,
+    // Every equation is a sum of monomials (variable indices, empty for 1) equal to 0.
+    pub equations: Vec<Vec<Vec<usize>>>,
+}
+
+impl Gf2System {
+    pub fn is_satisfied_by(&self, bits: &[bool]) -> bool {
+        self.equations.iter().all(|equation| {
+            !equation.iter().fold(false, |sum, monomial| {
+                sum ^ monomial.iter().all(|variable| bits[*variable])
+            })
+        })
+    }
+
+    // One polynomial per line in the notation of Sage, Magma and PolyBoRi.
+    pub fn to_anf_string(&self) -> String {
+        let mut system = format!("# variables: {}\n", self.variables.join(", "));
+        for equation in self.equations.iter() {
+            let monomials: Vec<String> = equation
+                .iter()
+                .map(|monomial| {
+                    if monomial.is_empty() {
+                        return "1".to_string();
+                    }
+                    monomial
+                        .iter()
+                        .map(|variable| self.variables[*variable].as_str())
+                        .collect::<Vec<&str>>()
+                        .join("*")
+                })
+                .collect();
+            if monomials.is_empty() {
+                system.push('0');
+            }
+            system.push_str(&monomials.join(" + "));
+            system.push('\n');
+        }
+        system
+    }
+
+    pub fn to_cnf(&self) -> Cnf {
+        let mut cnf = Cnf {
+            variables: self.variables.clone(),
+            auxiliary: Vec::new(),
+            clauses: Vec::new(),
+        };
+        let mut products: HashMap<(usize, usize), usize> = HashMap::new();
+        for equation in self.equations.iter() {
+            let mut parity = false;
+            let mut variables = Vec::new();
+            for monomial in equation.iter() {
+                match monomial[..] {
+                    [] => parity = !parity,
+                    [variable] => variables.push(variable),
+                    [a, b] => {
+                        let product = *products.entry((a, b)).or_insert_with(|| {
+                            let t = cnf.auxiliary_variable(AuxiliaryVariable::Product(a, b));
+                            cnf.clauses.push(vec![-literal(t), literal(a)]);
+                            cnf.clauses.push(vec![-literal(t), literal(b)]);
+                            cnf.clauses.push(vec![literal(t), -literal(a), -literal(b)]);
+                            t
+                        });
+                        variables.push(product);
+                    }
+                    _ => panic!("Expected monomials of degree at most 2"),
+                }
+            }
+            while variables.len() > XOR_CUT {
+                let mut chunk: Vec<usize> = variables.drain(..XOR_CUT - 1).collect();
+                let t = cnf.auxiliary_variable(AuxiliaryVariable::Sum(chunk.clone()));
+                chunk.push(t);
+                cnf.xor_clauses(&chunk, false);
+                variables.push(t);
+            }
+            cnf.xor_clauses(&variables, parity);
+        }
+        cnf
+    }
+}
+
+fn literal(variable: usize) -> i64 {
+    variable as i64 + 1
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuxiliaryVariable {
+    Product(usize, usize),
+    Sum(Vec<usize>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cnf {
+    // The GF(2) variables come first, then the auxiliary ones.
+    pub variables: Vec<String>,
+    pub auxiliary: Vec<AuxiliaryVariable>,
+    pub clauses: Vec<Vec<i64>>,
+}
+
+impl Cnf {
+    pub fn num_variables(&self) -> usize {
+        self.variables.len() + self.auxiliary.len()
+    }
+
+    fn auxiliary_variable(&mut self, definition: AuxiliaryVariable) -> usize {
+        self.auxiliary.push(definition);
+        self.num_variables() - 1
+    }
+
+    // XOR(variables) = parity, one clause per assignment with the wrong parity.
+    fn xor_clauses(&mut self, variables: &[usize], parity: bool) {
+        for assignment in 0..1usize << variables.len() {
+            if (assignment.count_ones() % 2 == 1) != parity {
+                self.clauses.push(
+                    variables
+                        .iter()
+                        .enumerate()
+                        .map(|(i, variable)| {
+                            if (assignment >> i) & 1 == 1 {
+                                -literal(*variable)
+                            } else {
+                                literal(*variable)
+                            }
+                        })
+                        .collect(),
+                );
+            }
+        }
+    }
+
+    // Adds the values of the auxiliary variables to an assignment of the GF(2) variables.
+    pub fn complete_assignment(&self, bits: &[bool]) -> Vec<bool> {
+        let mut assignment = bits.to_vec();
+        for definition in self.auxiliary.iter() {
+            let value = match definition {
+                AuxiliaryVariable::Product(a, b) => assignment[*a] && assignment[*b],
+                AuxiliaryVariable::Sum(variables) => variables
+                    .iter()
+                    .fold(false, |sum, variable| sum ^ assignment[*variable]),
+            };
+            assignment.push(value);
+        }
+        assignment
+    }
+
+    pub fn is_satisfied_by(&self, assignment: &[bool]) -> bool {
+        self.clauses.iter().all(|clause| {
+            clause
+                .iter()
+                .any(|literal| assignment[literal.unsigned_abs() as usize - 1] == (*literal > 0))
+        })
+    }
+
+    // The names of the GF(2) variables are given as comments.
+    pub fn to_dimacs(&self) -> String {
+        let mut dimacs = String::new();
+        for (i, name) in self.variables.iter().enumerate() {
+            dimacs.push_str(&format!("c {} {}\n", i + 1, name));
+        }
+        dimacs.push_str(&format!(
+            "p cnf {} {}\n",
+            self.num_variables(),
+            self.clauses.len()
+        ));
+        for clause in self.clauses.iter() {
+            for literal in clause.iter() {
+                dimacs.push_str(&format!("{} ", literal));
+            }
+            dimacs.push_str("0\n");
+        }
+        dimacs
+    }
+}
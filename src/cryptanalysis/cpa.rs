@@ -0,0 +1,178 @@
+// Correlation power analysis on (simulated) traces of the first round. For every
+// key byte and candidate k the leakage of SBOX[p ^ k] is predicted and correlated
+// (Pearson) with every sample; candidates are ranked by their largest |rho|.
+
+use crate::{
+    common::{key::AESKey, sbox::SBOX_TABLE},
+    cryptanalysis::power_trace::{LeakageModel, PowerTraceSimulator, TraceSet},
+};
+
+// Centred and scaled to unit length, the correlation of two normalized vectors is
+// their dot product. Constant vectors become all zeros.
+fn normalize(x: &[f64]) -> Vec<f64> {
+    let mean = x.iter().sum::<f64>() / x.len() as f64;
+    let centred: Vec<f64> = x.iter().map(|value| value - mean).collect();
+    let norm = centred
+        .iter()
+        .map(|value| value * value)
+        .sum::<f64>()
+        .sqrt();
+    if norm == 0.0 {
+        return vec![0.0; x.len()];
+    }
+    centred.iter().map(|value| value / norm).collect()
+}
+
+fn dot(x: &[f64], y: &[f64]) -> f64 {
+    x.iter().zip(y.iter()).map(|(a, b)| a * b).sum()
+}
+
+pub fn pearson_correlation(x: &[f64], y: &[f64]) -> f64 {
+    dot(&normalize(x), &normalize(y))
+}
+
+fn sbox(byte: u8) -> u8 {
+    SBOX_TABLE[(byte >> 4) as usize][(byte & 0x0f) as usize]
+}
+
+// Hamming distance hypotheses assume the S-box output overwrites p ^ k in the same register.
+pub fn hypothesis(model: LeakageModel, plaintext_byte: u8, key_guess: u8) -> f64 {
+    let added = plaintext_byte ^ key_guess;
+    match model {
+        LeakageModel::HammingWeight => sbox(added).count_ones() as f64,
+        LeakageModel::HammingDistance => (added ^ sbox(added)).count_ones() as f64,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CpaResult {
+    // scores[byte][guess] = max over samples of |rho|
+    pub scores: Vec<[f64; 256]>,
+}
+
+impl CpaResult {
+    // Candidates of one key byte, best first.
+    pub fn ranking(&self, byte: usize) -> Vec<u8> {
+        let mut candidates: Vec<u8> = (0..=255).collect();
+        candidates.sort_by(|a, b| {
+            self.scores[byte][*b as usize].total_cmp(&self.scores[byte][*a as usize])
+        });
+        candidates
+    }
+
+    pub fn best_key(&self) -> [u8; 16] {
+        let mut key = [0u8; 16];
+        for (byte, value) in key.iter_mut().enumerate() {
+            *value = self.ranking(byte)[0];
+        }
+        key
+    }
+
+    // 1 when the correct byte is the best candidate, 256 when it is the worst.
+    pub fn rank_of(&self, byte: usize, correct: u8) -> usize {
+        self.ranking(byte)
+            .iter()
+            .position(|candidate| *candidate == correct)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Expected {:#04x} among the candidates of byte {} but it was missing",
+                    correct, byte
+                )
+            })
+            + 1
+    }
+}
+
+pub fn cpa_attack(traces: &[Vec<f64>], plaintexts: &[[u8; 16]], model: LeakageModel) -> CpaResult {
+    if traces.len() != plaintexts.len() {
+        panic!(
+            "Expected one plaintext per trace but there were {} traces and {} plaintexts",
+            traces.len(),
+            plaintexts.len()
+        );
+    }
+    let samples = traces.first().map_or(0, |trace| trace.len());
+    let columns: Vec<Vec<f64>> = (0..samples)
+        .map(|s| normalize(&traces.iter().map(|trace| trace[s]).collect::<Vec<f64>>()))
+        .collect();
+
+    let mut scores = vec![[0f64; 256]; 16];
+    for (byte, byte_scores) in scores.iter_mut().enumerate() {
+        for (guess, score) in byte_scores.iter_mut().enumerate() {
+            let predicted: Vec<f64> = plaintexts
+                .iter()
+                .map(|plaintext| hypothesis(model, plaintext[byte], guess as u8))
+                .collect();
+            let predicted = normalize(&predicted);
+            *score = columns
+                .iter()
+                .map(|column| dot(&predicted, column).abs())
+                .fold(0.0, f64::max);
+        }
+    }
+    CpaResult { scores }
+}
+
+pub fn cpa_attack_set(set: &TraceSet, model: LeakageModel) -> CpaResult {
+    cpa_attack(&set.traces, &set.plaintexts, model)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CpaEvaluation {
+    pub traces: usize,
+    // Average rank of the correct key byte (1 is best) over all bytes and experiments.
+    pub guessing_entropy: f64,
+    // Fraction of experiments in which all 16 key bytes were ranked first.
+    pub success_rate: f64,
+}
+
+// Every experiment simulates a fresh set of traces (seed + experiment) and attacks
+// its first `traces` traces for every requested count.
+pub fn evaluate_cpa<T>(
+    simulator: &PowerTraceSimulator,
+    key: T,
+    correct_key: [u8; 16],
+    trace_counts: &[usize],
+    experiments: usize,
+) -> Vec<CpaEvaluation>
+where
+    T: AESKey + Copy,
+{
+    let max_traces = trace_counts.iter().copied().max().unwrap_or(0);
+    let sets: Vec<TraceSet> = (0..experiments)
+        .map(|experiment| {
+            PowerTraceSimulator {
+                seed: simulator.seed.wrapping_add(experiment as u64),
+                ..*simulator
+            }
+            .simulate(key, max_traces)
+        })
+        .collect();
+
+    trace_counts
+        .iter()
+        .map(|count| {
+            let mut rank_sum = 0;
+            let mut successes = 0;
+            for set in sets.iter() {
+                let result = cpa_attack(
+                    &set.traces[..*count],
+                    &set.plaintexts[..*count],
+                    simulator.model,
+                );
+                let ranks: Vec<usize> = (0..16)
+                    .map(|byte| result.rank_of(byte, correct_key[byte]))
+                    .collect();
+                rank_sum += ranks.iter().sum::<usize>();
+                if ranks.iter().all(|rank| *rank == 1) {
+                    successes += 1;
+                }
+            }
+            CpaEvaluation {
+                traces: *count,
+                guessing_entropy: rank_sum as f64 / (16 * experiments) as f64,
+                success_rate: successes as f64 / experiments as f64,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,142 @@
+// Differential fault analysis of AES-128 after Piret and Quisquater. A fault in one
+// byte of column c before the round-9 MixColumns spreads to the four bytes of that
+// column, which land in four different columns after the last ShiftRows. For every
+// faulted row and fault value the expected differences (2f, f, f, 3f rotated)
+// select the last round key bytes k with S^-1(C ^ k) ^ S^-1(C' ^ k) = delta, and a
+// couple of pairs per column leave a single candidate.
+
+use crate::common::{
+    cipher_operations::inv_sub_byte,
+    gf256::Gf256,
+    trace::{RoundObserver, RoundStep},
+    State,
+};
+
+const MIX_COLUMNS_ROW: [u8; 4] = [2, 3, 1, 1];
+
+// Flips `faults` (state index, XOR mask) right before `step` of `round`.
+#[derive(Clone, Debug)]
+pub struct FaultInjector {
+    pub round: usize,
+    pub step: RoundStep,
+    pub faults: Vec<(usize, u8)>,
+}
+
+impl FaultInjector {
+    // Before the MixColumns of round 9, the position assumed by the solver.
+    pub fn new(faults: Vec<(usize, u8)>) -> FaultInjector {
+        FaultInjector {
+            round: 9,
+            step: RoundStep::MixColumns,
+            faults,
+        }
+    }
+}
+
+impl RoundObserver for FaultInjector {
+    fn observe(&mut self, _round: usize, _step: RoundStep, _state: &State) {}
+    fn inject_fault(&mut self, round: usize, step: RoundStep, state: &mut State) {
+        if round == self.round && step == self.step {
+            for (index, mask) in self.faults.iter() {
+                state[*index] ^= mask;
+            }
+        }
+    }
+    fn injects_faults(&self) -> bool {
+        true
+    }
+}
+
+// Ciphertext index of row `row` of the faulted column after the final ShiftRows.
+fn ciphertext_index(column: usize, row: usize) -> usize {
+    4 * ((column + 4 - row) % 4) + row
+}
+
+// The faulted column, when exactly its four ciphertext bytes differ.
+pub fn fault_column(correct: &[u8; 16], faulty: &[u8; 16]) -> Option<usize> {
+    (0..4).find(|column| {
+        let indices: Vec<usize> = (0..4).map(|row| ciphertext_index(*column, row)).collect();
+        (0..16).all(|i| (correct[i] != faulty[i]) == indices.contains(&i))
+    })
+}
+
+// Candidates (k0, k1, k2, k3) for the last round key bytes of rows 0 to 3 of the
+// faulted column, i.e. key bytes ciphertext_index(column, row).
+pub fn column_key_candidates(correct: &[u8; 16], faulty: &[u8; 16], column: usize) -> Vec<[u8; 4]> {
+    // buckets[row][delta] = every k giving the difference delta before the last SubBytes
+    let buckets: Vec<Vec<Vec<u8>>> = (0..4)
+        .map(|row| {
+            let index = ciphertext_index(column, row);
+            let mut bucket = vec![Vec::new(); 256];
+            for k in 0..=255u8 {
+                let delta = inv_sub_byte(correct[index] ^ k) ^ inv_sub_byte(faulty[index] ^ k);
+                bucket[delta as usize].push(k);
+            }
+            bucket
+        })
+        .collect();
+
+    let mut candidates = Vec::new();
+    for faulted_row in 0..4 {
+        for fault in 1..=255u8 {
+            let deltas: Vec<u8> = (0..4)
+                .map(|row| {
+                    let coefficient = MIX_COLUMNS_ROW[(faulted_row + 4 - row) % 4];
+                    (Gf256(coefficient) * Gf256(fault)).value()
+                })
+                .collect();
+            for k0 in buckets[0][deltas[0] as usize].iter() {
+                for k1 in buckets[1][deltas[1] as usize].iter() {
+                    for k2 in buckets[2][deltas[2] as usize].iter() {
+                        for k3 in buckets[3][deltas[3] as usize].iter() {
+                            candidates.push([*k0, *k1, *k2, *k3]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DfaResult {
+    // Remaining candidates per faulted column, empty when no pair hit the column.
+    pub candidates: Vec<Vec<[u8; 4]>>,
+    // The last round key once every column is down to one candidate.
+    pub key: Option<[u8; 16]>,
+}
+
+// `pairs` holds (correct, faulty) ciphertexts of the same plaintext. Pairs whose
+// difference does not come from a single faulted column are ignored.
+pub fn recover_last_round_key(pairs: &[([u8; 16], [u8; 16])]) -> DfaResult {
+    let mut candidates: Vec<Vec<[u8; 4]>> = vec![Vec::new(); 4];
+    let mut hit = [false; 4];
+    for (correct, faulty) in pairs.iter() {
+        let Some(column) = fault_column(correct, faulty) else {
+            continue;
+        };
+        let found = column_key_candidates(correct, faulty, column);
+        if hit[column] {
+            candidates[column].retain(|candidate| found.binary_search(candidate).is_ok());
+        } else {
+            candidates[column] = found;
+            hit[column] = true;
+        }
+    }
+
+    let key = if candidates.iter().all(|column| column.len() == 1) {
+        let mut key = [0u8; 16];
+        for (column, column_candidates) in candidates.iter().enumerate() {
+            for row in 0..4 {
+                key[ciphertext_index(column, row)] = column_candidates[0][row];
+            }
+        }
+        Some(key)
+    } else {
+        None
+    };
+    DfaResult { candidates, key }
+}
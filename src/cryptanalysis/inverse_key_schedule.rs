@@ -0,0 +1,85 @@
+// Runs the AES key schedule backwards. Every word satisfies
+// w[i] = w[i - Nk] ^ f(w[i - 1], i), so any Nk consecutive words determine the
+// whole schedule: one round key for AES-128, two consecutive ones for AES-192/256.
+
+use crate::common::{
+    cipher_operations::get_next_key, rijndael::rijndael_num_rounds, utils::decode_to_hex_vector,
+};
+
+// `words` holds Nk consecutive schedule words starting at word index `first_word`.
+pub fn invert_key_schedule(words: &[[u8; 4]], first_word: usize) -> Vec<[u8; 4]> {
+    let key_length = words.len();
+    let mut window = words.to_vec();
+
+    for i in (key_length..first_word + key_length).rev() {
+        // The window holds w[i - Nk + 1..=i], the XOR with f(w[i - 1], i) gives back w[i - Nk].
+        let previous = get_next_key(
+            window[key_length - 2],
+            window[key_length - 1],
+            key_length,
+            i,
+        );
+        window.pop();
+        window.insert(0, previous);
+    }
+    window
+}
+
+// Nr + 1 round keys from the Nk words at index `first_word`: backwards to the key,
+// then forwards through the rest of the schedule.
+fn expand_from_words(words: &[[u8; 4]], first_word: usize) -> Vec<[u8; 4]> {
+    let key_length = words.len();
+    let mut schedule = invert_key_schedule(words, first_word);
+    for i in key_length..4 * (rijndael_num_rounds(key_length, 4) + 1) {
+        let next = get_next_key(schedule[i - 1], schedule[i - key_length], key_length, i);
+        schedule.push(next);
+    }
+    schedule
+}
+
+// `round_keys` are consecutive round keys (hex) starting with round `round`, only
+// the first Nk words are used.
+fn parse_round_keys(round_keys: &str, round: usize, key_length: usize) -> Vec<[u8; 4]> {
+    if ![4, 5, 6, 7, 8].contains(&key_length) {
+        panic!(
+            "Expected a key length of 4 to 8 words but it was {}",
+            key_length
+        );
+    }
+    let num_rounds = rijndael_num_rounds(key_length, 4);
+    let last_round = (4 * (num_rounds + 1) - key_length) / 4;
+    if round > last_round {
+        panic!(
+            "Expected a round of at most {} for {} rounds and {} key words but it was {}",
+            last_round, num_rounds, key_length, round
+        );
+    }
+    let bytes = decode_to_hex_vector(round_keys);
+    if bytes.len() < 4 * key_length {
+        panic!(
+            "Expected at least {} bytes of round keys but it was {}",
+            4 * key_length,
+            bytes.len()
+        );
+    }
+    bytes
+        .chunks(4)
+        .take(key_length)
+        .map(|word| [word[0], word[1], word[2], word[3]])
+        .collect()
+}
+
+pub fn recover_master_key(round_keys: &str, round: usize, key_length: usize) -> String {
+    let words = parse_round_keys(round_keys, round, key_length);
+    let key = invert_key_schedule(&words, 4 * round);
+    hex::encode(key.concat())
+}
+
+// All Nr + 1 round keys (hex), round 0 first.
+pub fn recover_key_schedule(round_keys: &str, round: usize, key_length: usize) -> Vec<String> {
+    let words = parse_round_keys(round_keys, round, key_length);
+    expand_from_words(&words, 4 * round)
+        .chunks(4)
+        .map(|round_key| hex::encode(round_key.concat()))
+        .collect()
+}
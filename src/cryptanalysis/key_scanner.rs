@@ -0,0 +1,197 @@
+// Finds expanded AES key schedules in memory images, in the spirit of aeskeyfind
+// (Halderman et al., "Lest We Remember"). At every byte offset the words are
+// checked against w[i] = w[i - Nk] ^ f(w[i - 1], i); the number of bits in which
+// memory differs from these relations is a cheap filter. For the offsets that pass,
+// the key is recovered by running the schedule backwards from every window of Nk
+// words and keeping the one whose full schedule is closest to memory, so bits that
+// decayed (cold boot) in the key itself are corrected as well.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::{
+    common::cipher_operations::get_next_key,
+    cryptanalysis::inverse_key_schedule::invert_key_schedule,
+};
+
+// A flipped bit shows up in at most three relations, one of them through an S-box.
+const RELATION_ERRORS_PER_BIT: u32 = 10;
+
+// Files are scanned in chunks that overlap by the longest (AES-256) schedule.
+const SCAN_CHUNK_SIZE: usize = 1 << 20;
+const LONGEST_SCHEDULE_BYTES: usize = 4 * 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FoundKey {
+    pub offset: usize,
+    // In words, 4, 6 or 8.
+    pub key_length: usize,
+    pub key: String,
+    // Bits in which memory differs from the schedule of `key`.
+    pub bit_errors: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyScanner {
+    pub key_lengths: Vec<usize>,
+    pub max_bit_errors: u32,
+}
+
+fn schedule_words(key_length: usize) -> usize {
+    4 * (key_length + 7)
+}
+
+fn word_at(memory: &[u8], offset: usize, i: usize) -> [u8; 4] {
+    let start = offset + 4 * i;
+    [
+        memory[start],
+        memory[start + 1],
+        memory[start + 2],
+        memory[start + 3],
+    ]
+}
+
+fn word_distance(a: [u8; 4], b: [u8; 4]) -> u32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x ^ y).count_ones())
+        .sum()
+}
+
+fn expand(key: &[[u8; 4]], words: usize) -> Vec<[u8; 4]> {
+    let key_length = key.len();
+    let mut schedule = key.to_vec();
+    for i in key_length..words {
+        let next = get_next_key(schedule[i - 1], schedule[i - key_length], key_length, i);
+        schedule.push(next);
+    }
+    schedule
+}
+
+impl KeyScanner {
+    // AES-128, AES-192 and AES-256 schedules.
+    pub fn new(max_bit_errors: u32) -> KeyScanner {
+        KeyScanner {
+            key_lengths: vec![4, 6, 8],
+            max_bit_errors,
+        }
+    }
+
+    // Stops as soon as the relations are off by more than `limit` bits.
+    fn relation_errors(memory: &[u8], offset: usize, key_length: usize, limit: u32) -> Option<u32> {
+        let mut errors = 0;
+        for i in key_length..schedule_words(key_length) {
+            let expected = get_next_key(
+                word_at(memory, offset, i - 1),
+                word_at(memory, offset, i - key_length),
+                key_length,
+                i,
+            );
+            errors += word_distance(expected, word_at(memory, offset, i));
+            if errors > limit {
+                return None;
+            }
+        }
+        Some(errors)
+    }
+
+    fn recover(memory: &[u8], offset: usize, key_length: usize) -> FoundKey {
+        let words = schedule_words(key_length);
+        let memory_words: Vec<[u8; 4]> = (0..words).map(|i| word_at(memory, offset, i)).collect();
+        (0..=words - key_length)
+            .map(|first_word| {
+                let key = invert_key_schedule(
+                    &memory_words[first_word..first_word + key_length],
+                    first_word,
+                );
+                let bit_errors = expand(&key, words)
+                    .iter()
+                    .zip(memory_words.iter())
+                    .map(|(expected, actual)| word_distance(*expected, *actual))
+                    .sum();
+                (key, bit_errors)
+            })
+            .min_by_key(|(_, bit_errors)| *bit_errors)
+            .map(|(key, bit_errors)| FoundKey {
+                offset,
+                key_length,
+                key: hex::encode(key.concat()),
+                bit_errors,
+            })
+            .unwrap_or_else(|| panic!("Expected at least one window of {} words", key_length))
+    }
+
+    pub fn scan(&self, memory: &[u8]) -> Vec<FoundKey> {
+        if let Some(key_length) = self.key_lengths.iter().find(|k| ![4, 6, 8].contains(*k)) {
+            panic!(
+                "Expected a key length of 4, 6 or 8 words but it was {}",
+                key_length
+            );
+        }
+        let limit = RELATION_ERRORS_PER_BIT * self.max_bit_errors;
+        let mut found = Vec::new();
+        for offset in 0..memory.len() {
+            for key_length in self.key_lengths.iter() {
+                if offset + 4 * schedule_words(*key_length) > memory.len() {
+                    continue;
+                }
+                if KeyScanner::relation_errors(memory, offset, *key_length, limit).is_none() {
+                    continue;
+                }
+                let key = KeyScanner::recover(memory, offset, *key_length);
+                if key.bit_errors <= self.max_bit_errors {
+                    found.push(key);
+                }
+            }
+        }
+        found
+    }
+
+    pub fn scan_file<P>(&self, path: P) -> io::Result<Vec<FoundKey>>
+    where
+        P: AsRef<Path>,
+    {
+        self.scan_reader(File::open(path)?, SCAN_CHUNK_SIZE)
+    }
+
+    // Reads `chunk_size` bytes at a time and keeps the last 240 bytes for the next
+    // chunk, a schedule starting there is only reported once the chunk after it has
+    // been read. Offsets are relative to the start of the reader.
+    pub fn scan_reader<R>(&self, mut reader: R, chunk_size: usize) -> io::Result<Vec<FoundKey>>
+    where
+        R: Read,
+    {
+        if chunk_size == 0 {
+            panic!("Expected a chunk size above 0 but it was {}", chunk_size);
+        }
+        let mut found = Vec::new();
+        let mut buffer = Vec::with_capacity(chunk_size + LONGEST_SCHEDULE_BYTES);
+        let mut base = 0;
+        loop {
+            let read = reader
+                .by_ref()
+                .take(chunk_size as u64)
+                .read_to_end(&mut buffer)?;
+            let end_of_file = read < chunk_size;
+            let complete = if end_of_file {
+                buffer.len()
+            } else {
+                buffer.len().saturating_sub(LONGEST_SCHEDULE_BYTES)
+            };
+            for mut key in self.scan(&buffer) {
+                if key.offset < complete {
+                    key.offset += base;
+                    found.push(key);
+                }
+            }
+            if end_of_file {
+                return Ok(found);
+            }
+            buffer.drain(..complete);
+            base += complete;
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// First-order Boolean masking of the reference cipher: every state byte x is held
+// as two shares with x = s0 ^ s1. ShiftRows, MixColumns and AddRoundKey are linear
+// and work share by share. SubBytes uses table recomputation: the state is remasked
+// with one fresh input mask m for all bytes and looked up in
+// T[v] = S(v ^ m) ^ m', so a single share never depends on an unmasked value.
+//
+// Observers see share 0 only, which is what a first-order probe (one sample per
+// intermediate value) gets; the Output step reports the recombined ciphertext.
+
+use crate::{
+    common::{
+        cipher_operations::{add_round_key, mix_columns, sub_byte, sub_rows},
+        key::AESKey,
+        trace::{NoObserver, RoundObserver, RoundStep},
+        utils::decode_to_hex_vector,
+        State,
+    },
+    cryptanalysis::{power_trace::SimulationRng, reduced_round::ReducedRoundAES},
+};
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MaskedState {
+    pub shares: [State; 2],
+}
+
+impl MaskedState {
+    pub fn mask(state: State, rng: &mut SimulationRng) -> MaskedState {
+        let mask = State::from_hex_vector(&rng.next_bytes::<16>());
+        let mut masked = state;
+        masked
+            .iter_mut()
+            .zip(mask.iter())
+            .for_each(|(byte, m)| *byte ^= m);
+        MaskedState {
+            shares: [masked, mask],
+        }
+    }
+
+    pub fn unmask(&self) -> State {
+        let mut state = self.shares[0];
+        state
+            .iter_mut()
+            .zip(self.shares[1].iter())
+            .for_each(|(byte, m)| *byte ^= m);
+        state
+    }
+}
+
+// T[v] = S(v ^ input_mask) ^ output_mask, recomputed for every round.
+pub fn masked_sbox_table(input_mask: u8, output_mask: u8) -> [u8; 256] {
+    let mut table = [0u8; 256];
+    for (v, entry) in table.iter_mut().enumerate() {
+        *entry = sub_byte(v as u8 ^ input_mask) ^ output_mask;
+    }
+    table
+}
+
+fn masked_sub_bytes(state: &mut MaskedState, rng: &mut SimulationRng) {
+    let [input_mask, output_mask] = rng.next_bytes::<2>();
+    let table = masked_sbox_table(input_mask, output_mask);
+    for i in 0..16 {
+        // Add the new mask before removing the old one, x is never left unmasked.
+        state.shares[0][i] ^= input_mask;
+        state.shares[0][i] ^= state.shares[1][i];
+        state.shares[0][i] = table[state.shares[0][i] as usize];
+        state.shares[1][i] = output_mask;
+    }
+}
+
+pub struct MaskedAES {
+    cipher: ReducedRoundAES,
+}
+
+impl MaskedAES {
+    pub fn new<T>(key: &T) -> MaskedAES
+    where
+        T: AESKey,
+    {
+        MaskedAES {
+            cipher: ReducedRoundAES::new(key, key.num_rounds(), false),
+        }
+    }
+
+    pub fn encrypt_state_observed<O>(
+        &self,
+        state: State,
+        rng: &mut SimulationRng,
+        observer: &mut O,
+    ) -> State
+    where
+        O: RoundObserver,
+    {
+        let rounds = self.cipher.num_rounds();
+        observer.begin(false);
+        let mut state = MaskedState::mask(state, rng);
+        observer.observe(0, RoundStep::Input, &state.shares[0]);
+        add_round_key(&mut state.shares[0], self.cipher.get_round_subkey(0));
+
+        for round in 1..=rounds {
+            observer.observe(round, RoundStep::Start, &state.shares[0]);
+            masked_sub_bytes(&mut state, rng);
+            observer.observe(round, RoundStep::SubBytes, &state.shares[0]);
+            state.shares = state.shares.map(sub_rows);
+            observer.observe(round, RoundStep::ShiftRows, &state.shares[0]);
+            if round < rounds {
+                state.shares = state.shares.map(mix_columns);
+                observer.observe(round, RoundStep::MixColumns, &state.shares[0]);
+            }
+            add_round_key(&mut state.shares[0], self.cipher.get_round_subkey(round));
+        }
+
+        let output = state.unmask();
+        observer.observe(rounds, RoundStep::Output, &output);
+        output
+    }
+
+    pub fn encrypt_block(&self, message: &str, rng: &mut SimulationRng) -> String {
+        let block: [u8; 16] =
+            decode_to_hex_vector(message)
+                .try_into()
+                .unwrap_or_else(|v: Vec<u8>| {
+                    panic!("Expected a Vec of length {} but it was {}", 16, v.len())
+                });
+        let mut observer = NoObserver;
+        let state = self.encrypt_state_observed(State::from_hex_vector(&block), rng, &mut observer);
+        hex::encode(*state)
+    }
+}
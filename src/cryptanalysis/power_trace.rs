@@ -0,0 +1,244 @@
+// Simulated power traces for side-channel labs. A `LeakageObserver` is plugged
+// into `encrypt_block_observed` and turns the state after every AddRoundKey
+// (the round start) and every SubBytes into one sample per byte: its Hamming
+// weight, or the Hamming distance to the previous value of the state register,
+// plus Gaussian noise.
+
+use std::{fs, io, path::Path};
+
+use crate::{
+    common::{
+        key::AESKey,
+        trace::{RoundObserver, RoundStep},
+        State,
+    },
+    cryptanalysis::masking::MaskedAES,
+    encrypt_block_observed, AESOptimization,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LeakageModel {
+    HammingWeight,
+    HammingDistance,
+}
+
+// splitmix64, good enough for simulations and reproducible from a seed.
+#[derive(Clone, Debug)]
+pub struct SimulationRng {
+    state: u64,
+}
+
+impl SimulationRng {
+    pub fn new(seed: u64) -> SimulationRng {
+        SimulationRng { state: seed }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+    pub fn next_bytes<const N: usize>(&mut self) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        for chunk in bytes.chunks_mut(8) {
+            let value = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&value[..chunk.len()]);
+        }
+        bytes
+    }
+    // Uniform in (0, 1].
+    pub fn next_f64(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+    // Box-Muller transform.
+    pub fn next_gaussian(&mut self) -> f64 {
+        let u1 = self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+pub struct LeakageObserver<'a> {
+    model: LeakageModel,
+    noise: f64,
+    rounds: usize,
+    rng: &'a mut SimulationRng,
+    register: State,
+    pub samples: Vec<f64>,
+}
+
+impl<'a> LeakageObserver<'a> {
+    pub fn new(
+        model: LeakageModel,
+        noise: f64,
+        rounds: usize,
+        rng: &'a mut SimulationRng,
+    ) -> LeakageObserver<'a> {
+        LeakageObserver {
+            model,
+            noise,
+            rounds,
+            rng,
+            register: State::default(),
+            samples: Vec::new(),
+        }
+    }
+
+    fn leak(&mut self, state: &State) {
+        for (i, byte) in state.iter().enumerate() {
+            let leakage = match self.model {
+                LeakageModel::HammingWeight => byte.count_ones(),
+                LeakageModel::HammingDistance => (byte ^ self.register[i]).count_ones(),
+            };
+            let noise = if self.noise > 0.0 {
+                self.noise * self.rng.next_gaussian()
+            } else {
+                0.0
+            };
+            self.samples.push(leakage as f64 + noise);
+        }
+        self.register = *state;
+    }
+}
+
+impl RoundObserver for LeakageObserver<'_> {
+    fn begin(&mut self, _inverse: bool) {
+        self.samples.clear();
+    }
+    fn observe(&mut self, round: usize, step: RoundStep, state: &State) {
+        match step {
+            RoundStep::Input => self.register = *state,
+            RoundStep::Start | RoundStep::SubBytes if round <= self.rounds => self.leak(state),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TraceSet {
+    pub plaintexts: Vec<[u8; 16]>,
+    pub traces: Vec<Vec<f64>>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PowerTraceSimulator {
+    pub model: LeakageModel,
+    pub noise: f64,
+    // Number of rounds that leak, every round gives 32 samples (AddRoundKey output
+    // of the previous round followed by SubBytes).
+    pub rounds: usize,
+    pub seed: u64,
+    // Runs the first-order masked implementation instead of the reference one.
+    pub masked: bool,
+}
+
+impl PowerTraceSimulator {
+    pub fn new(model: LeakageModel, noise: f64) -> PowerTraceSimulator {
+        PowerTraceSimulator {
+            model,
+            noise,
+            rounds: 1,
+            seed: 0x5eed,
+            masked: false,
+        }
+    }
+
+    pub fn samples_per_trace(&self) -> usize {
+        32 * self.rounds
+    }
+
+    pub fn simulate<T>(&self, key: T, count: usize) -> TraceSet
+    where
+        T: AESKey + Copy,
+    {
+        let mut rng = SimulationRng::new(self.seed);
+        let mut set = TraceSet::default();
+        let masked = self.masked.then(|| MaskedAES::new(&key));
+        for _ in 0..count {
+            let plaintext: [u8; 16] = rng.next_bytes();
+            let mut noise_rng = SimulationRng::new(rng.next_u64());
+            let mut observer =
+                LeakageObserver::new(self.model, self.noise, self.rounds, &mut noise_rng);
+            match &masked {
+                Some(cipher) => {
+                    let mut mask_rng = SimulationRng::new(rng.next_u64());
+                    cipher.encrypt_state_observed(
+                        State::from_hex_vector(&plaintext),
+                        &mut mask_rng,
+                        &mut observer,
+                    );
+                }
+                None => {
+                    encrypt_block_observed(
+                        &hex::encode(plaintext),
+                        key,
+                        AESOptimization::NoOptimization,
+                        &mut observer,
+                    );
+                }
+            }
+            set.traces.push(observer.samples);
+            set.plaintexts.push(plaintext);
+        }
+        set
+    }
+}
+
+// NPY format version 1.0: magic, version, little endian header length, a Python
+// dict literal padded with spaces to a multiple of 64 bytes, then the raw data.
+fn npy_header(descr: &str, rows: usize, columns: usize) -> Vec<u8> {
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        descr, rows, columns
+    );
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes
+}
+
+fn check_rows<T>(rows: &[Vec<T>]) -> usize {
+    let columns = rows.first().map_or(0, |row| row.len());
+    if let Some(row) = rows.iter().find(|row| row.len() != columns) {
+        panic!(
+            "Expected rows of length {} but it was {}",
+            columns,
+            row.len()
+        );
+    }
+    columns
+}
+
+pub fn traces_to_npy(traces: &[Vec<f64>]) -> Vec<u8> {
+    let columns = check_rows(traces);
+    let mut bytes = npy_header("<f8", traces.len(), columns);
+    for value in traces.iter().flatten() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+pub fn plaintexts_to_npy(plaintexts: &[[u8; 16]]) -> Vec<u8> {
+    let mut bytes = npy_header("|u1", plaintexts.len(), 16);
+    bytes.extend(plaintexts.iter().flatten());
+    bytes
+}
+
+pub fn write_traces_npy<P>(path: P, traces: &[Vec<f64>]) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(path, traces_to_npy(traces))
+}
+
+pub fn write_plaintexts_npy<P>(path: P, plaintexts: &[[u8; 16]]) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    fs::write(path, plaintexts_to_npy(plaintexts))
+}
@@ -0,0 +1,111 @@
+// AES with a configurable number of rounds (1 to 14) for square / integral and
+// differential attack exercises. The key schedule is the regular one, simply run
+// for as many rounds as requested.
+
+use crate::common::{
+    cipher_operations::{
+        add_round_key, get_next_key, inv_mix_columns, inv_shift_rows, inv_sub_bytes, mix_columns,
+        sub_bytes, sub_rows,
+    },
+    key::AESKey,
+    utils::decode_to_hex_vector,
+    State,
+};
+
+pub const MAX_ROUNDS: usize = 14;
+
+#[derive(Clone, Debug)]
+pub struct ReducedRoundAES {
+    rounds: usize,
+    final_mix_columns: bool,
+    round_keys: Vec<[u8; 4]>,
+}
+
+impl ReducedRoundAES {
+    pub fn new<T>(key: &T, rounds: usize, final_mix_columns: bool) -> ReducedRoundAES
+    where
+        T: AESKey,
+    {
+        if rounds == 0 || rounds > MAX_ROUNDS {
+            panic!(
+                "Expected between 1 and {} rounds but it was {}",
+                MAX_ROUNDS, rounds
+            );
+        }
+        let key_length = key.key_length();
+        let mut round_keys: Vec<[u8; 4]> = (0..key_length).map(|i| key.get_key_as_row(i)).collect();
+
+        for i in key_length..4 * (rounds + 1) {
+            let next = get_next_key(round_keys[i - 1], round_keys[i - key_length], key_length, i);
+            round_keys.push(next);
+        }
+        round_keys.truncate(4 * (rounds + 1));
+
+        ReducedRoundAES {
+            rounds,
+            final_mix_columns,
+            round_keys,
+        }
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.rounds
+    }
+
+    pub fn final_mix_columns(&self) -> bool {
+        self.final_mix_columns
+    }
+
+    #[allow(clippy::identity_op)]
+    pub fn get_round_subkey(&self, round: usize) -> [[u8; 4]; 4] {
+        [
+            self.round_keys[4 * round + 0],
+            self.round_keys[4 * round + 1],
+            self.round_keys[4 * round + 2],
+            self.round_keys[4 * round + 3],
+        ]
+    }
+
+    pub fn encrypt_state(&self, mut state: State) -> State {
+        add_round_key(&mut state, self.get_round_subkey(0));
+        for round in 1..=self.rounds {
+            state = sub_bytes(state);
+            state = sub_rows(state);
+            if round < self.rounds || self.final_mix_columns {
+                state = mix_columns(state);
+            }
+            add_round_key(&mut state, self.get_round_subkey(round));
+        }
+        state
+    }
+
+    pub fn decrypt_state(&self, mut state: State) -> State {
+        for round in (1..=self.rounds).rev() {
+            add_round_key(&mut state, self.get_round_subkey(round));
+            if round < self.rounds || self.final_mix_columns {
+                inv_mix_columns(&mut state);
+            }
+            state = inv_shift_rows(state);
+            state = inv_sub_bytes(state);
+        }
+        add_round_key(&mut state, self.get_round_subkey(0));
+        state
+    }
+
+    pub fn encrypt_block(&self, message: &str) -> String {
+        hex::encode(*self.encrypt_state(block_to_state(message)))
+    }
+
+    pub fn decrypt_block(&self, ciphertext: &str) -> String {
+        hex::encode(*self.decrypt_state(block_to_state(ciphertext)))
+    }
+}
+
+fn block_to_state(block: &str) -> State {
+    let block: [u8; 16] = decode_to_hex_vector(block)
+        .try_into()
+        .unwrap_or_else(|v: Vec<u8>| {
+            panic!("Expected a Vec of length {} but it was {}", 16, v.len())
+        });
+    State::from_hex_vector(&block)
+}
@@ -0,0 +1,326 @@
+// XOR differences through the AES key schedule for related-key attacks. Word i of
+// the schedule is w[i - Nk] ^ f(w[i - 1], i), where f applies SubWord every Nk words
+// (and in the middle of the Nk words for Nk > 6). The linear part moves a key
+// difference around unchanged, every S-box with a non-zero input difference is
+// active and makes its output difference depend on the key.
+//
+// `propagate_key_difference` works without a key and tracks for every byte whether
+// the difference is zero, a known value, non-zero but unknown, or anything at all.
+// `key_pair_difference` runs `get_next_key` on a key and on the key XOR the
+// difference and gives the actual differences.
+
+use crate::common::{
+    cipher_operations::{get_next_key, sub_byte},
+    math::rot_word,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ByteDifference {
+    Zero,
+    Fixed(u8),
+    // Non-zero, the output of an active S-box.
+    Active,
+    // Any value, zero included.
+    Unknown,
+}
+
+impl ByteDifference {
+    pub fn from_value(value: u8) -> ByteDifference {
+        if value == 0 {
+            ByteDifference::Zero
+        } else {
+            ByteDifference::Fixed(value)
+        }
+    }
+
+    pub fn xor(self, other: ByteDifference) -> ByteDifference {
+        match (self, other) {
+            (ByteDifference::Zero, difference) | (difference, ByteDifference::Zero) => difference,
+            (ByteDifference::Fixed(a), ByteDifference::Fixed(b)) => {
+                ByteDifference::from_value(a ^ b)
+            }
+            _ => ByteDifference::Unknown,
+        }
+    }
+
+    pub fn sub_byte(self) -> ByteDifference {
+        match self {
+            ByteDifference::Zero => ByteDifference::Zero,
+            ByteDifference::Fixed(_) | ByteDifference::Active => ByteDifference::Active,
+            ByteDifference::Unknown => ByteDifference::Unknown,
+        }
+    }
+
+    // Certainly not zero, an S-box with this input difference is active.
+    pub fn is_non_zero(self) -> bool {
+        matches!(self, ByteDifference::Fixed(_) | ByteDifference::Active)
+    }
+
+    // A possible value of a concrete difference.
+    pub fn allows(self, value: u8) -> bool {
+        match self {
+            ByteDifference::Zero => value == 0,
+            ByteDifference::Fixed(fixed) => value == fixed,
+            ByteDifference::Active => value != 0,
+            ByteDifference::Unknown => true,
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            ByteDifference::Zero => '.',
+            ByteDifference::Fixed(_) => 'F',
+            ByteDifference::Active => 'A',
+            ByteDifference::Unknown => '?',
+        }
+    }
+}
+
+pub type WordDifference = [ByteDifference; 4];
+
+// Bytes of the (rotated) previous word that go through an S-box for word i.
+fn sbox_bytes(key_length: usize, i: usize) -> &'static [usize] {
+    if i.is_multiple_of(key_length) || (key_length > 6 && i % key_length == 4) {
+        &[0, 1, 2, 3]
+    } else {
+        &[]
+    }
+}
+
+// The on-the-fly step of `get_next_key` on differences, returns the new word and
+// the input differences of its S-boxes.
+pub fn next_word_difference(
+    prev_word: WordDifference,
+    current_word: WordDifference,
+    key_length: usize,
+    expansion_counter: usize,
+) -> (WordDifference, Vec<ByteDifference>) {
+    let mut temp = prev_word;
+    if expansion_counter.is_multiple_of(key_length) {
+        // RotWord, as in `rot_word`.
+        temp = [temp[1], temp[2], temp[3], temp[0]];
+    }
+    let inputs: Vec<ByteDifference> = sbox_bytes(key_length, expansion_counter)
+        .iter()
+        .map(|byte| temp[*byte])
+        .collect();
+    if !inputs.is_empty() {
+        temp = temp.map(ByteDifference::sub_byte);
+    }
+    // The round constant is the same for both keys.
+    let mut next = current_word;
+    for (byte, difference) in next.iter_mut().zip(temp.iter()) {
+        *byte = byte.xor(*difference);
+    }
+    (next, inputs)
+}
+
+fn key_length(bytes: usize) -> usize {
+    if !bytes.is_multiple_of(4) || !(16..=32).contains(&bytes) {
+        panic!("Expected a key of 4 to 8 words but it had {} bytes", bytes);
+    }
+    bytes / 4
+}
+
+fn schedule_words(key_length: usize) -> usize {
+    4 * (key_length + 7)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyScheduleDifference {
+    pub key_length: usize,
+    pub words: Vec<WordDifference>,
+    // (word, input difference) for every S-box of the key schedule.
+    pub sboxes: Vec<(usize, ByteDifference)>,
+}
+
+impl KeyScheduleDifference {
+    pub fn num_rounds(&self) -> usize {
+        self.key_length + 6
+    }
+
+    pub fn round_key(&self, round: usize) -> [WordDifference; 4] {
+        [
+            self.words[4 * round],
+            self.words[4 * round + 1],
+            self.words[4 * round + 2],
+            self.words[4 * round + 3],
+        ]
+    }
+
+    // S-boxes with a non-zero input difference.
+    pub fn active_sboxes(&self) -> usize {
+        self.sboxes
+            .iter()
+            .filter(|(_, input)| input.is_non_zero())
+            .count()
+    }
+
+    // S-boxes whose input difference may or may not be zero.
+    pub fn possibly_active_sboxes(&self) -> usize {
+        self.sboxes
+            .iter()
+            .filter(|(_, input)| *input == ByteDifference::Unknown)
+            .count()
+    }
+
+    // Round keys with a difference that is not known to be zero.
+    pub fn affected_round_keys(&self) -> Vec<usize> {
+        (0..=self.num_rounds())
+            .filter(|round| {
+                self.round_key(*round)
+                    .iter()
+                    .flatten()
+                    .any(|byte| *byte != ByteDifference::Zero)
+            })
+            .collect()
+    }
+
+    // One line per round key, words separated by spaces: '.' no difference, 'F' a
+    // known difference, 'A' the output of an active S-box and '?' unknown.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for round in 0..=self.num_rounds() {
+            let words: Vec<String> = self
+                .round_key(round)
+                .iter()
+                .map(|word| word.iter().map(|byte| byte.symbol()).collect())
+                .collect();
+            let sboxes: Vec<&(usize, ByteDifference)> = self
+                .sboxes
+                .iter()
+                .filter(|(word, _)| word / 4 == round)
+                .collect();
+            let active = sboxes
+                .iter()
+                .filter(|(_, input)| input.is_non_zero())
+                .count();
+            let possible = sboxes
+                .iter()
+                .filter(|(_, input)| *input == ByteDifference::Unknown)
+                .count();
+            report.push_str(&format!(
+                "round[{:>2}] {}  {} active, {} possibly active S-boxes\n",
+                round,
+                words.join(" "),
+                active,
+                possible
+            ));
+        }
+        report
+    }
+}
+
+// `difference` holds the Nk key words of the difference as bytes.
+pub fn propagate_key_difference(difference: &[u8]) -> KeyScheduleDifference {
+    let key_length = key_length(difference.len());
+    let mut words: Vec<WordDifference> = difference
+        .chunks(4)
+        .map(|word| [0, 1, 2, 3].map(|byte| ByteDifference::from_value(word[byte])))
+        .collect();
+    let mut sboxes = Vec::new();
+    for i in key_length..schedule_words(key_length) {
+        let (next, inputs) =
+            next_word_difference(words[i - 1], words[i - key_length], key_length, i);
+        sboxes.extend(inputs.into_iter().map(|input| (i, input)));
+        words.push(next);
+    }
+    KeyScheduleDifference {
+        key_length,
+        words,
+        sboxes,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SBoxDifference {
+    pub word: usize,
+    pub input: u8,
+    pub output: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyPairDifference {
+    pub key_length: usize,
+    pub words: Vec<[u8; 4]>,
+    pub sboxes: Vec<SBoxDifference>,
+}
+
+impl KeyPairDifference {
+    pub fn active_sboxes(&self) -> usize {
+        self.sboxes.iter().filter(|sbox| sbox.input != 0).count()
+    }
+
+    pub fn affected_round_keys(&self) -> Vec<usize> {
+        (0..=self.key_length + 6)
+            .filter(|round| {
+                self.words[4 * round..4 * round + 4]
+                    .iter()
+                    .flatten()
+                    .any(|byte| *byte != 0)
+            })
+            .collect()
+    }
+}
+
+// Expands `key` and `key ^ difference` word by word with `get_next_key`.
+pub fn key_pair_difference(key: &[u8], difference: &[u8]) -> KeyPairDifference {
+    if key.len() != difference.len() {
+        panic!(
+            "Expected a difference of {} bytes but it was {}",
+            key.len(),
+            difference.len()
+        );
+    }
+    let key_length = key_length(key.len());
+    let word = |bytes: &[u8]| [bytes[0], bytes[1], bytes[2], bytes[3]];
+    let mut first: Vec<[u8; 4]> = key.chunks(4).map(word).collect();
+    let mut second: Vec<[u8; 4]> = key
+        .iter()
+        .zip(difference.iter())
+        .map(|(k, d)| k ^ d)
+        .collect::<Vec<u8>>()
+        .chunks(4)
+        .map(word)
+        .collect();
+
+    let mut sboxes = Vec::new();
+    for i in key_length..schedule_words(key_length) {
+        if !sbox_bytes(key_length, i).is_empty() {
+            let (a, b) = if i.is_multiple_of(key_length) {
+                (rot_word(first[i - 1]), rot_word(second[i - 1]))
+            } else {
+                (first[i - 1], second[i - 1])
+            };
+            for (x, y) in a.iter().zip(b.iter()) {
+                sboxes.push(SBoxDifference {
+                    word: i,
+                    input: x ^ y,
+                    output: sub_byte(*x) ^ sub_byte(*y),
+                });
+            }
+        }
+        first.push(get_next_key(
+            first[i - 1],
+            first[i - key_length],
+            key_length,
+            i,
+        ));
+        second.push(get_next_key(
+            second[i - 1],
+            second[i - key_length],
+            key_length,
+            i,
+        ));
+    }
+
+    KeyPairDifference {
+        key_length,
+        words: first
+            .iter()
+            .zip(second.iter())
+            .map(|(a, b)| [a[0] ^ b[0], a[1] ^ b[1], a[2] ^ b[2], a[3] ^ b[3]])
+            .collect(),
+        sboxes,
+    }
+}
@@ -0,0 +1,160 @@
+// Cryptographic properties of 8-bit S-boxes: difference distribution table,
+// linear approximation table and the figures derived from them. For the AES S-box
+// the differential uniformity is 4, the nonlinearity 112 and the algebraic degree 7.
+
+use std::fmt::Display;
+
+use crate::common::sbox::SBOX_TABLE;
+
+pub type SBox = [u8; 256];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SBoxProperties {
+    pub is_permutation: bool,
+    pub differential_uniformity: u16,
+    pub nonlinearity: u16,
+    pub algebraic_degree: u32,
+    pub fixed_points: Vec<u8>,
+    pub opposite_fixed_points: Vec<u8>,
+}
+
+pub fn sbox_from_table(table: &[[u8; 16]; 16]) -> SBox {
+    let mut sbox = [0u8; 256];
+    for (x, value) in sbox.iter_mut().enumerate() {
+        *value = table[x >> 4][x & 0x0f];
+    }
+    sbox
+}
+
+pub fn aes_sbox() -> SBox {
+    sbox_from_table(&SBOX_TABLE)
+}
+
+// ddt[a][b] = #{x : S(x) ^ S(x ^ a) = b}
+pub fn difference_distribution_table(sbox: &SBox) -> Vec<[u16; 256]> {
+    let mut table = vec![[0u16; 256]; 256];
+    for (a, row) in table.iter_mut().enumerate() {
+        for x in 0..256 {
+            row[(sbox[x] ^ sbox[x ^ a]) as usize] += 1;
+        }
+    }
+    table
+}
+
+// lat[a][b] = #{x : a.x = b.S(x)} - 128, computed per output mask b with a fast
+// Walsh-Hadamard transform of (-1)^(b.S(x)), which gives twice the bias.
+pub fn linear_approximation_table(sbox: &SBox) -> Vec<[i16; 256]> {
+    let mut table = vec![[0i16; 256]; 256];
+    for b in 0..256 {
+        let mut walsh: Vec<i16> = sbox
+            .iter()
+            .map(|value| 1 - 2 * ((b as u8 & value).count_ones() & 1) as i16)
+            .collect();
+        let mut step = 1;
+        while step < 256 {
+            for x in 0..256 {
+                if x & step == 0 {
+                    let (u, v) = (walsh[x], walsh[x | step]);
+                    walsh[x] = u + v;
+                    walsh[x | step] = u - v;
+                }
+            }
+            step <<= 1;
+        }
+        for (a, row) in table.iter_mut().enumerate() {
+            row[b] = walsh[a] / 2;
+        }
+    }
+    table
+}
+
+pub fn differential_uniformity(ddt: &[[u16; 256]]) -> u16 {
+    ddt.iter()
+        .skip(1)
+        .flat_map(|row| row.iter())
+        .copied()
+        .max()
+        .unwrap_or(0)
+}
+
+// 128 minus the largest bias of a non-trivial component function.
+pub fn nonlinearity(lat: &[[i16; 256]]) -> u16 {
+    let max_bias = lat
+        .iter()
+        .flat_map(|row| row.iter().skip(1))
+        .map(|bias| bias.unsigned_abs())
+        .max()
+        .unwrap_or(0);
+    128 - max_bias
+}
+
+// Highest degree of the algebraic normal form over all output bits, the ANF is
+// obtained with the binary Moebius transform.
+pub fn algebraic_degree(sbox: &SBox) -> u32 {
+    let mut degree = 0;
+    for bit in 0..8 {
+        let mut anf: Vec<u8> = sbox.iter().map(|value| (value >> bit) & 1).collect();
+        let mut step = 1;
+        while step < 256 {
+            for x in 0..256 {
+                if x & step != 0 {
+                    anf[x] ^= anf[x ^ step];
+                }
+            }
+            step <<= 1;
+        }
+        for (monomial, coefficient) in anf.iter().enumerate() {
+            if *coefficient == 1 {
+                degree = degree.max(monomial.count_ones());
+            }
+        }
+    }
+    degree
+}
+
+pub fn fixed_points(sbox: &SBox) -> Vec<u8> {
+    (0..=255u8).filter(|x| sbox[*x as usize] == *x).collect()
+}
+
+pub fn opposite_fixed_points(sbox: &SBox) -> Vec<u8> {
+    (0..=255u8).filter(|x| sbox[*x as usize] == !*x).collect()
+}
+
+pub fn is_permutation(sbox: &SBox) -> bool {
+    let mut seen = [false; 256];
+    for value in sbox.iter() {
+        seen[*value as usize] = true;
+    }
+    seen.iter().all(|s| *s)
+}
+
+pub fn analyse_sbox(sbox: &SBox) -> SBoxProperties {
+    SBoxProperties {
+        is_permutation: is_permutation(sbox),
+        differential_uniformity: differential_uniformity(&difference_distribution_table(sbox)),
+        nonlinearity: nonlinearity(&linear_approximation_table(sbox)),
+        algebraic_degree: algebraic_degree(sbox),
+        fixed_points: fixed_points(sbox),
+        opposite_fixed_points: opposite_fixed_points(sbox),
+    }
+}
+
+// One line per input mask / difference, with the output masks / differences as header.
+pub fn table_to_csv<T>(table: &[[T; 256]]) -> String
+where
+    T: Display,
+{
+    let mut csv = String::from("in\\out");
+    for b in 0..256 {
+        csv.push_str(&format!(",{:02x}", b));
+    }
+    csv.push('\n');
+    for (a, row) in table.iter().enumerate() {
+        csv.push_str(&format!("{:02x}", a));
+        for entry in row.iter() {
+            csv.push_str(&format!(",{}", entry));
+        }
+        csv.push('\n');
+    }
+    csv
+}
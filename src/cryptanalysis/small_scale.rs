@@ -0,0 +1,301 @@
+// Small-scale AES SR(n, r, c, e) of Cid, Murphy and Robshaw: n rounds over a state
+// of r rows and c columns of e-bit words. SR* omits MixColumns in the last round,
+// SR*(10, 4, 4, 8) is AES-128. Follows the definitions used by Sage (`mq.SR`).
+
+use crate::common::{
+    cipher_operations::{inv_sub_byte_no_mem, inverse_with, mult_with, sbox_no_mem},
+    constants::{
+        AES_POLYNOMIAL, AES_POLYNOMIAL_DEGREE, SMALL_SCALE_POLYNOMIAL,
+        SMALL_SCALE_POLYNOMIAL_DEGREE,
+    },
+    utils::decode_to_hex_vector,
+};
+
+fn rotate_nibble(x: u8, n: u32) -> u8 {
+    ((x << n) | (x >> (4 - n))) & 0x0f
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SmallScaleAES {
+    rounds: usize,
+    rows: usize,
+    columns: usize,
+    word_size: usize,
+    star: bool,
+}
+
+impl SmallScaleAES {
+    pub fn new(
+        rounds: usize,
+        rows: usize,
+        columns: usize,
+        word_size: usize,
+        star: bool,
+    ) -> SmallScaleAES {
+        if rounds == 0 || rounds > 10 {
+            panic!("Expected between 1 and 10 rounds but it was {}", rounds);
+        }
+        // MixColumns is only defined for 1, 2 and 4 rows.
+        if ![1, 2, 4].contains(&rows) {
+            panic!("Expected 1, 2 or 4 rows but it was {}", rows);
+        }
+        if columns == 0 || columns > 4 {
+            panic!("Expected between 1 and 4 columns but it was {}", columns);
+        }
+        if word_size != 4 && word_size != 8 {
+            panic!(
+                "Expected a word size of 4 or 8 bits but it was {}",
+                word_size
+            );
+        }
+        SmallScaleAES {
+            rounds,
+            rows,
+            columns,
+            word_size,
+            star,
+        }
+    }
+
+    pub fn num_rounds(&self) -> usize {
+        self.rounds
+    }
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+    pub fn word_size(&self) -> usize {
+        self.word_size
+    }
+    pub fn block_length(&self) -> usize {
+        self.rows * self.columns
+    }
+    pub fn star(&self) -> bool {
+        self.star
+    }
+
+    fn modulus(&self) -> (u16, usize) {
+        if self.word_size == 4 {
+            (SMALL_SCALE_POLYNOMIAL, SMALL_SCALE_POLYNOMIAL_DEGREE)
+        } else {
+            (AES_POLYNOMIAL, AES_POLYNOMIAL_DEGREE)
+        }
+    }
+
+    pub fn mult(&self, a: u8, b: u8) -> u8 {
+        let (modulus, degree) = self.modulus();
+        mult_with(a, b, modulus, degree)
+    }
+
+    pub fn inverse(&self, a: u8) -> u8 {
+        let (modulus, degree) = self.modulus();
+        inverse_with(a, modulus, degree)
+    }
+
+    // For e = 4 the affine map is x + x <<< 2 + x <<< 3 + 0x6 on nibbles.
+    pub fn sbox(&self, word: u8) -> u8 {
+        if self.word_size == 8 {
+            return sbox_no_mem(word);
+        }
+        let q = self.inverse(word);
+        q ^ rotate_nibble(q, 2) ^ rotate_nibble(q, 3) ^ 0x6
+    }
+
+    // The inverse affine map is x + x <<< 1 + x <<< 2, applied to y + 0x6.
+    pub fn inverse_sbox(&self, word: u8) -> u8 {
+        if self.word_size == 8 {
+            return inv_sub_byte_no_mem(word);
+        }
+        if word >> 4 != 0 {
+            panic!("{:#x} is not a 4-bit word", word);
+        }
+        let q = word ^ 0x6;
+        self.inverse(q ^ rotate_nibble(q, 1) ^ rotate_nibble(q, 2))
+    }
+
+    // The state is stored column by column, word (row, column) is at column * rows + row.
+    pub(crate) fn at(&self, row: usize, column: usize) -> usize {
+        column * self.rows + row
+    }
+
+    fn sub_bytes(&self, state: &mut [u8]) {
+        for word in state.iter_mut() {
+            *word = self.sbox(*word);
+        }
+    }
+
+    fn inv_sub_bytes(&self, state: &mut [u8]) {
+        for word in state.iter_mut() {
+            *word = self.inverse_sbox(*word);
+        }
+    }
+
+    pub(crate) fn shift_rows(&self, state: &mut [u8], inverse: bool) {
+        let original = state.to_vec();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let source = if inverse {
+                    (column + self.columns - row % self.columns) % self.columns
+                } else {
+                    (column + row) % self.columns
+                };
+                state[self.at(row, column)] = original[self.at(row, source)];
+            }
+        }
+    }
+
+    // Row 0 of the circulant matrix: (1) for one row, (x + 1, x) for two and
+    // (x, x + 1, 1, 1) for four rows.
+    fn mix_columns_coefficients(&self) -> Vec<u8> {
+        match self.rows {
+            1 => vec![1],
+            2 => vec![3, 2],
+            _ => vec![2, 3, 1, 1],
+        }
+    }
+
+    pub(crate) fn mix_columns(&self, state: &mut [u8]) {
+        let coefficients = self.mix_columns_coefficients();
+        for column in 0..self.columns {
+            let original: Vec<u8> = (0..self.rows)
+                .map(|row| state[self.at(row, column)])
+                .collect();
+            for row in 0..self.rows {
+                let mut value = 0;
+                for (k, word) in original.iter().enumerate() {
+                    value ^= self.mult(coefficients[(k + self.rows - row) % self.rows], *word);
+                }
+                state[self.at(row, column)] = value;
+            }
+        }
+    }
+
+    // The matrices have order 1, 2 and 4 in characteristic 2, so the inverse is M^(rows - 1).
+    fn inv_mix_columns(&self, state: &mut [u8]) {
+        for _ in 1..self.rows {
+            self.mix_columns(state);
+        }
+    }
+
+    fn add_round_key(state: &mut [u8], round_key: &[u8]) {
+        for (word, key_word) in state.iter_mut().zip(round_key.iter()) {
+            *word ^= key_word;
+        }
+    }
+
+    pub(crate) fn round_constant(&self, round: usize) -> u8 {
+        let mut constant = 1;
+        for _ in 1..round {
+            constant = self.mult(constant, 2);
+        }
+        constant
+    }
+
+    // Round key i from round key i - 1: the last column is rotated up by one row,
+    // substituted and added (with the round constant in row 0) to the running sum
+    // of the previous round key columns.
+    fn next_round_key(&self, previous: &[u8], round: usize) -> Vec<u8> {
+        let mut round_key = vec![0u8; self.block_length()];
+        for row in 0..self.rows {
+            let mut value = self.sbox(previous[self.at((row + 1) % self.rows, self.columns - 1)]);
+            if row == 0 {
+                value ^= self.round_constant(round);
+            }
+            for column in 0..self.columns {
+                value ^= previous[self.at(row, column)];
+                round_key[self.at(row, column)] = value;
+            }
+        }
+        round_key
+    }
+
+    pub fn key_schedule(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        self.check_length(key);
+        let mut round_keys = vec![key.to_vec()];
+        for round in 1..=self.rounds {
+            let next = self.next_round_key(&round_keys[round - 1], round);
+            round_keys.push(next);
+        }
+        round_keys
+    }
+
+    pub(crate) fn check_length(&self, words: &[u8]) {
+        if words.len() != self.block_length() {
+            panic!(
+                "Expected {} words but it was {}",
+                self.block_length(),
+                words.len()
+            );
+        }
+        if let Some(word) = words.iter().find(|w| (**w as usize) >> self.word_size != 0) {
+            panic!("{:#x} does not fit in {} bits", word, self.word_size);
+        }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8], key: &[u8]) -> Vec<u8> {
+        self.check_length(plaintext);
+        let round_keys = self.key_schedule(key);
+        let mut state = plaintext.to_vec();
+
+        Self::add_round_key(&mut state, &round_keys[0]);
+        for (round, round_key) in round_keys.iter().enumerate().skip(1) {
+            self.sub_bytes(&mut state);
+            self.shift_rows(&mut state, false);
+            if round < self.rounds || !self.star {
+                self.mix_columns(&mut state);
+            }
+            Self::add_round_key(&mut state, round_key);
+        }
+        state
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8], key: &[u8]) -> Vec<u8> {
+        self.check_length(ciphertext);
+        let round_keys = self.key_schedule(key);
+        let mut state = ciphertext.to_vec();
+
+        for (round, round_key) in round_keys.iter().enumerate().skip(1).rev() {
+            Self::add_round_key(&mut state, round_key);
+            if round < self.rounds || !self.star {
+                self.inv_mix_columns(&mut state);
+            }
+            self.shift_rows(&mut state, true);
+            self.inv_sub_bytes(&mut state);
+        }
+        Self::add_round_key(&mut state, &round_keys[0]);
+        state
+    }
+
+    // Hex strings with one digit per word for e = 4 and two digits per word for e = 8.
+    pub fn words_from_hex(&self, data: &str) -> Vec<u8> {
+        if self.word_size == 8 {
+            return decode_to_hex_vector(data);
+        }
+        data.chars()
+            .map(|digit| {
+                digit
+                    .to_digit(16)
+                    .unwrap_or_else(|| panic!("{} is not a hex digit", digit)) as u8
+            })
+            .collect()
+    }
+
+    pub fn words_to_hex(&self, words: &[u8]) -> String {
+        if self.word_size == 8 {
+            return hex::encode(words);
+        }
+        words.iter().map(|word| format!("{:x}", word)).collect()
+    }
+
+    pub fn encrypt_block(&self, message: &str, key: &str) -> String {
+        let ciphertext = self.encrypt(&self.words_from_hex(message), &self.words_from_hex(key));
+        self.words_to_hex(&ciphertext)
+    }
+
+    pub fn decrypt_block(&self, ciphertext: &str, key: &str) -> String {
+        let message = self.decrypt(&self.words_from_hex(ciphertext), &self.words_from_hex(key));
+        self.words_to_hex(&message)
+    }
+}
@@ -0,0 +1,97 @@
+// The Square (integral) attack on 4-round AES without the final MixColumns. In a
+// Lambda-set one byte takes all 256 values and the others are constant; after three
+// rounds every byte of the state sums (XOR) to zero. Undoing the last round for one
+// byte under a key guess k, sum S^-1(C ^ k) = 0 holds for the right k and for a
+// wrong one with probability 1/256, so a few sets leave one candidate per byte.
+
+use crate::{
+    common::{cipher_operations::inv_sub_byte, State},
+    cryptanalysis::reduced_round::ReducedRoundAES,
+};
+
+// The 256 states equal to `base` except for byte `active`, which runs through 0..=255.
+pub fn lambda_set(base: [u8; 16], active: usize) -> Vec<State> {
+    if active >= 16 {
+        panic!(
+            "Expected an active byte between 0 and 15 but it was {}",
+            active
+        );
+    }
+    (0..=255u8)
+        .map(|value| {
+            let mut block = base;
+            block[active] = value;
+            State::from_hex_vector(&block)
+        })
+        .collect()
+}
+
+pub fn encrypt_lambda_set(cipher: &ReducedRoundAES, set: &[State]) -> Vec<State> {
+    set.iter()
+        .map(|state| cipher.encrypt_state(*state))
+        .collect()
+}
+
+// The guesses k for last round key byte `index` that make the Lambda-set balanced.
+pub fn balanced_key_bytes(ciphertexts: &[State], index: usize) -> Vec<u8> {
+    (0..=255u8)
+        .filter(|k| {
+            ciphertexts.iter().fold(0, |sum, ciphertext| {
+                sum ^ inv_sub_byte(ciphertext[index] ^ k)
+            }) == 0
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SquareResult {
+    // Remaining guesses for every byte of the last round key.
+    pub candidates: Vec<Vec<u8>>,
+    // The last round key once every byte is down to one candidate.
+    pub key: Option<[u8; 16]>,
+}
+
+impl SquareResult {
+    // Number of last round keys that are still possible.
+    pub fn remaining_keys(&self) -> u128 {
+        self.candidates
+            .iter()
+            .map(|candidates| candidates.len() as u128)
+            .product()
+    }
+}
+
+// One Lambda-set (active byte 0) per entry of `bases`, the candidates of all sets are intersected.
+pub fn square_attack(cipher: &ReducedRoundAES, bases: &[[u8; 16]]) -> SquareResult {
+    if cipher.num_rounds() != 4 {
+        panic!(
+            "Expected 4 rounds but it had {} rounds",
+            cipher.num_rounds()
+        );
+    }
+    if cipher.final_mix_columns() {
+        panic!("Expected no MixColumns in the last round but it had one");
+    }
+    let mut candidates: Vec<Vec<u8>> = vec![(0..=255).collect(); 16];
+    for base in bases.iter() {
+        let ciphertexts = encrypt_lambda_set(cipher, &lambda_set(*base, 0));
+        for (index, byte_candidates) in candidates.iter_mut().enumerate() {
+            let balanced = balanced_key_bytes(&ciphertexts, index);
+            byte_candidates.retain(|k| balanced.contains(k));
+        }
+    }
+
+    let key = if candidates
+        .iter()
+        .all(|byte_candidates| byte_candidates.len() == 1)
+    {
+        let mut key = [0u8; 16];
+        for (byte, byte_candidates) in key.iter_mut().zip(candidates.iter()) {
+            *byte = byte_candidates[0];
+        }
+        Some(key)
+    } else {
+        None
+    };
+    SquareResult { candidates, key }
+}
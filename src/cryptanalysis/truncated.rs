@@ -0,0 +1,244 @@
+// Truncated differential trails: only which bytes of the state have a non-zero
+// difference is tracked, as a 16-bit pattern with bit 4c + r for row r of column c.
+// SubBytes keeps the pattern (every set bit is an active S-box), ShiftRows moves it
+// and MixColumns maps a column with a > 0 active bytes to any column with b > 0
+// active bytes and a + b >= 5, its branch number. Both layers are taken from
+// `sub_rows` and `mix_columns`.
+//
+// The minimum number of active S-boxes over r rounds is found by dynamic
+// programming over all 2^16 patterns, MixColumns is handled one column at a time.
+
+use std::sync::OnceLock;
+
+use crate::common::{
+    cipher_operations::{mix_columns, sub_rows},
+    gf256::Gf256,
+    State,
+};
+
+pub type Pattern = u16;
+
+pub fn shift_rows_pattern(pattern: Pattern) -> Pattern {
+    let mut state = State::get_empty_state();
+    for (i, byte) in state.iter_mut().enumerate() {
+        *byte = ((pattern >> i) & 1) as u8;
+    }
+    sub_rows(state)
+        .iter()
+        .enumerate()
+        .fold(0, |shifted, (i, byte)| shifted | (*byte as Pattern) << i)
+}
+
+fn column_of(pattern: Pattern, column: usize) -> u16 {
+    (pattern >> (4 * column)) & 0xf
+}
+
+// matrix[i][j] is the coefficient of input row j in output row i.
+pub fn mix_columns_matrix() -> [[u8; 4]; 4] {
+    let mut matrix = [[0u8; 4]; 4];
+    for j in 0..4 {
+        let mut unit = [0u8; 16];
+        unit[j] = 1;
+        let mixed = mix_columns(State::from_hex_vector(&unit));
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row[j] = mixed[i];
+        }
+    }
+    matrix
+}
+
+fn determinant(matrix: &[Vec<Gf256>]) -> Gf256 {
+    if matrix.len() == 1 {
+        return matrix[0][0];
+    }
+    // Expansion along the first row, signs do not matter in characteristic 2.
+    (0..matrix.len()).fold(Gf256::ZERO, |sum, column| {
+        let minor: Vec<Vec<Gf256>> = matrix[1..]
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != column)
+                    .map(|(_, value)| *value)
+                    .collect()
+            })
+            .collect();
+        sum + matrix[0][column] * determinant(&minor)
+    })
+}
+
+// Every square submatrix is non-singular.
+pub fn is_mds(matrix: &[[u8; 4]; 4]) -> bool {
+    (1u16..16).all(|rows| {
+        (1u16..16)
+            .filter(|columns| columns.count_ones() == rows.count_ones())
+            .all(|columns| {
+                let submatrix: Vec<Vec<Gf256>> = (0..4)
+                    .filter(|i| (rows >> i) & 1 == 1)
+                    .map(|i| {
+                        (0..4)
+                            .filter(|j| (columns >> j) & 1 == 1)
+                            .map(|j| Gf256(matrix[i][j]))
+                            .collect()
+                    })
+                    .collect();
+                determinant(&submatrix) != Gf256::ZERO
+            })
+    })
+}
+
+// The minimum of wt(x) + wt(Mx) over the 255 * 4 columns x with one non-zero byte.
+// That is an upper bound on the branch number and equals it for an MDS matrix,
+// where it reaches one more than the number of rows.
+pub fn branch_number(matrix: &[[u8; 4]; 4]) -> u32 {
+    let mut branch = u32::MAX;
+    for j in 0..4 {
+        for x in 1..=255u8 {
+            let output = matrix
+                .iter()
+                .filter(|row| Gf256(row[j]) * Gf256(x) != Gf256::ZERO)
+                .count() as u32;
+            branch = branch.min(1 + output);
+        }
+    }
+    branch
+}
+
+// The branch number of `mix_columns`, computed on first use.
+fn mix_columns_branch_number() -> u32 {
+    static BRANCH_NUMBER: OnceLock<u32> = OnceLock::new();
+    *BRANCH_NUMBER.get_or_init(|| {
+        let matrix = mix_columns_matrix();
+        if !is_mds(&matrix) {
+            panic!("Expected an MDS matrix for MixColumns");
+        }
+        branch_number(&matrix)
+    })
+}
+
+fn column_transition(input: u16, output: u16, branch_number: u32) -> bool {
+    match (input, output) {
+        (0, 0) => true,
+        (0, _) | (_, 0) => false,
+        _ => input.count_ones() + output.count_ones() >= branch_number,
+    }
+}
+
+// All patterns MixColumns can give for `pattern` (after ShiftRows).
+pub fn mix_columns_transitions(pattern: Pattern) -> Vec<Pattern> {
+    let branch = mix_columns_branch_number();
+    let mut transitions: Vec<Pattern> = vec![0];
+    for column in 0..4 {
+        let input = column_of(pattern, column);
+        let outputs: Vec<u16> = (0..16)
+            .filter(|output| column_transition(input, *output, branch))
+            .collect();
+        transitions = transitions
+            .iter()
+            .flat_map(|pattern| {
+                outputs
+                    .iter()
+                    .map(move |output| pattern | output << (4 * column))
+            })
+            .collect();
+    }
+    transitions
+}
+
+fn is_round_transition(from: Pattern, to: Pattern, branch: u32) -> bool {
+    let shifted = shift_rows_pattern(from);
+    (0..4)
+        .all(|column| column_transition(column_of(shifted, column), column_of(to, column), branch))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TruncatedTrail {
+    // The input pattern of SubBytes in every round.
+    pub patterns: Vec<Pattern>,
+    pub active_sboxes: u32,
+}
+
+// A trail over `rounds` rounds with the fewest active S-boxes, the first pattern is not zero.
+pub fn min_active_sboxes(rounds: usize) -> TruncatedTrail {
+    if rounds == 0 {
+        panic!("Expected at least 1 round but it was {}", rounds);
+    }
+    let branch = mix_columns_branch_number();
+    let shift: Vec<Pattern> = (0..=Pattern::MAX).map(shift_rows_pattern).collect();
+
+    // costs[r][p]: fewest active S-boxes in the rounds before round r + 1 when its input pattern is p.
+    let mut first = vec![0u32; 1 << 16];
+    first[0] = u32::MAX;
+    let mut costs = vec![first];
+    for _ in 1..rounds {
+        let previous = costs
+            .last()
+            .unwrap_or_else(|| panic!("Expected a first round"));
+        let mut next = vec![u32::MAX; 1 << 16];
+        for (pattern, cost) in previous.iter().enumerate() {
+            if *cost != u32::MAX {
+                let shifted = shift[pattern] as usize;
+                next[shifted] = next[shifted].min(cost + pattern.count_ones());
+            }
+        }
+        // One column after the other goes from its ShiftRows output to its MixColumns output.
+        for column in 0..4 {
+            let mut mixed = vec![u32::MAX; 1 << 16];
+            for (pattern, cost) in next.iter().enumerate() {
+                if *cost == u32::MAX {
+                    continue;
+                }
+                let input = column_of(pattern as Pattern, column);
+                let rest = pattern & !(0xf << (4 * column));
+                for output in 0..16u16 {
+                    if column_transition(input, output, branch) {
+                        let target = rest | (output as usize) << (4 * column);
+                        mixed[target] = mixed[target].min(*cost);
+                    }
+                }
+            }
+            next = mixed;
+        }
+        costs.push(next);
+    }
+
+    let total = |cost: u32, pattern: usize| cost.saturating_add(pattern.count_ones());
+    let last = &costs[rounds - 1];
+    let mut pattern = (1..1 << 16)
+        .min_by_key(|pattern| total(last[*pattern], *pattern))
+        .unwrap_or(1);
+    let active_sboxes = total(last[pattern], pattern);
+
+    // Walks back through patterns that reach the same cost.
+    let mut patterns = vec![pattern as Pattern];
+    for round in (0..rounds - 1).rev() {
+        let target = costs[round + 1][pattern];
+        pattern = (1..1 << 16)
+            .find(|candidate| {
+                total(costs[round][*candidate], *candidate) == target
+                    && is_round_transition(*candidate as Pattern, pattern as Pattern, branch)
+            })
+            .unwrap_or_else(|| panic!("No predecessor of {:#06x} in round {}", pattern, round + 1));
+        patterns.insert(0, pattern as Pattern);
+    }
+    TruncatedTrail {
+        patterns,
+        active_sboxes,
+    }
+}
+
+// The state as 4 lines, 'x' for an active byte.
+pub fn pattern_to_string(pattern: Pattern) -> String {
+    let mut grid = String::new();
+    for row in 0..4 {
+        for column in 0..4 {
+            grid.push(if (pattern >> (4 * column + row)) & 1 == 1 {
+                'x'
+            } else {
+                '.'
+            });
+        }
+        grid.push('\n');
+    }
+    grid
+}
@@ -1,31 +1,81 @@
 //AES Implementation based on FIPS PUB 197
 
 use common::{
+    aes_ni::{
+        hardware_decrypt_state, hardware_encrypt_state, hardware_key_expansion, HardwareRoundKeys,
+    },
+    bitslice::{
+        bitsliced_inv_sub_bytes, bitsliced_key_expansion, bitsliced_op, bitsliced_op_inverse,
+        bitsliced_sub_bytes,
+    },
     cipher_operations::{
-        add_round_key, add_round_key_no_mem, inv_mix_columns, inv_shift_rows, inv_sub_bytes,
-        inv_sub_bytes_no_mem, merged_op, merged_op_inverse, mix_columns, sub_bytes,
-        sub_bytes_no_mem, sub_rows,
+        add_round_key, add_round_key_no_mem, compact_inv_sub_bytes, compact_merged_op,
+        compact_merged_op_inverse, compact_sub_bytes, get_next_key, inv_mix_columns,
+        inv_shift_rows, inv_sub_bytes, inv_sub_bytes_no_mem, merged_op, merged_op_inverse,
+        merged_op_inverse_with, merged_op_with, mix_columns, sub_bytes, sub_bytes_no_mem, sub_rows,
     },
-    key::AESKey,
+    sbox_spec::SpecRounds,
+    trace::NoObserver,
     utils::decode_to_hex_vector,
-    State,
+    vpaes::{
+        vector_permute_inv_sub_bytes, vector_permute_key_expansion, vector_permute_op,
+        vector_permute_op_inverse, vector_permute_sub_bytes, VectorPermuteSupport,
+    },
 };
 
 mod common;
+pub use common::{
+    aes_ni::{hardware_decrypt_blocks, hardware_encrypt_blocks, is_hardware_supported},
+    ctr_drbg::{CtrDrbg, CtrDrbgError, RngCore, MAX_BYTES_PER_REQUEST, MAX_RESEED_INTERVAL},
+    gf256::Gf256,
+    key::{AESKey, KeyNk4, KeyNk5, KeyNk6, KeyNk7, KeyNk8},
+    modes::{
+        decrypt_ctr, decrypt_xts, decrypt_xts_with_optimization, encrypt_ctr,
+        encrypt_ctr_with_optimization, encrypt_ecb, encrypt_ecb_with_optimization, encrypt_xts,
+        encrypt_xts_with_optimization,
+    },
+    padding::{pad_message_pkcs7, unpad_message_pkcs7},
+    rijndael::{rijndael_num_rounds, Rijndael},
+    sbox_spec::{is_irreducible, FieldParams, SBoxSpec},
+    trace::{RoundObserver, RoundStep, Trace, TraceEntry},
+    trace_export::{trace_to_html, trace_to_json},
+    State,
+};
+pub mod cryptanalysis;
 
 pub enum AESKeyLength {
     Nk4,
+    Nk5,
     Nk6,
+    Nk7,
     Nk8,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum AESOptimization {
     NoOptimization,
     MemoryEfficient,
     SpeedEfficient,
+    CompactSpeedEfficient,
+    Bitsliced,
+    Hardware,
+    VectorPermute,
 }
 
 pub fn get_round_subkey_for_no_mem<T>(round: usize, key: &T, inverse_columns: bool) -> [[u8; 4]; 4]
+where
+    T: AESKey,
+{
+    round_subkey_for_no_mem(round, key, inverse_columns, None)
+}
+
+// `spec` replaces the S-box and round constants of the key schedule and InvMixColumns.
+fn round_subkey_for_no_mem<T>(
+    round: usize,
+    key: &T,
+    inverse_columns: bool,
+    spec: Option<&SpecRounds>,
+) -> [[u8; 4]; 4]
 where
     T: AESKey,
 {
@@ -38,7 +88,7 @@ where
 
     for _ in 0..round + 1 {
         key_state = State::from_words([
-            key_buffer[(expansion_counter + 0) % key.key_length()],
+            key_buffer[expansion_counter % key.key_length()],
             key_buffer[(expansion_counter + 1) % key.key_length()],
             key_buffer[(expansion_counter + 2) % key.key_length()],
             key_buffer[(expansion_counter + 3) % key.key_length()],
@@ -49,39 +99,77 @@ where
             &mut key_buffer,
             expansion_counter,
             key.key_length(),
+            |prev, current, length, counter| next_key(spec, prev, current, length, counter),
         );
 
         expansion_counter = new_expansion_counter;
     }
     if inverse_columns {
-        inv_mix_columns(&mut key_state);
+        key_state = spec_mix_columns(key_state, spec, true);
     }
 
     for (j, _) in key_state.into_iter().step_by(4).enumerate() {
         let index = (j + expansion_counter) % key.key_length();
 
         key_buffer[index] = [
-            key_state[4 * j + 0],
+            key_state[4 * j],
             key_state[4 * j + 1],
             key_state[4 * j + 2],
             key_state[4 * j + 3],
         ];
     }
     [
-        key_buffer[(expansion_counter + 0) % key.key_length()],
+        key_buffer[expansion_counter % key.key_length()],
         key_buffer[(expansion_counter + 1) % key.key_length()],
         key_buffer[(expansion_counter + 2) % key.key_length()],
         key_buffer[(expansion_counter + 3) % key.key_length()],
     ]
 }
 
-pub fn decrypt_block<'a, T>(
-    ciphertext: &'a str,
+fn check_fault_injection<O>(observer: &O, optimization: AESOptimization)
+where
+    O: RoundObserver,
+{
+    if observer.injects_faults() && optimization != AESOptimization::NoOptimization {
+        panic!(
+            "Expected NoOptimization for fault injection but it was {:?}",
+            optimization
+        );
+    }
+}
+
+pub fn decrypt_block<T>(ciphertext: &str, key: T, optimization: AESOptimization) -> String
+where
+    T: AESKey,
+{
+    decrypt_block_observed(ciphertext, key, optimization, &mut NoObserver)
+}
+
+pub fn decrypt_block_observed<T, O>(
+    ciphertext: &str,
+    key: T,
+    optimization: AESOptimization,
+    observer: &mut O,
+) -> String
+where
+    T: AESKey,
+    O: RoundObserver,
+{
+    decrypt_block_rounds(ciphertext, key, optimization, observer, None)
+}
+
+// `spec` replaces the S-box, MixColumns and key schedule of the NoOptimization,
+// MemoryEfficient and SpeedEfficient rounds.
+fn decrypt_block_rounds<T, O>(
+    ciphertext: &str,
     mut key: T,
     optimization: AESOptimization,
+    observer: &mut O,
+    spec: Option<&SpecRounds>,
 ) -> String
 where
     T: AESKey,
+    O: RoundObserver,
 {
     let ciphertext: [u8; 16] =
         decode_to_hex_vector(ciphertext)
@@ -92,37 +180,80 @@ where
     let mut state = State::from_hex_vector(&ciphertext);
     let num_rounds = key.num_rounds();
     let mut round = num_rounds;
-
+    check_fault_injection(observer, optimization);
+    observer.begin(true);
+    observer.observe(0, RoundStep::Input, &state);
+
+    let backend_keys = expand_key(&mut key, optimization, true, spec);
+    // The hardware rounds stay in registers, only the input and output are reported.
+    if optimization == AESOptimization::Hardware {
+        state = hardware_decrypt_state(state, &key, &backend_keys.hardware);
+        observer.observe(num_rounds, RoundStep::Output, &state);
+        return hex::encode(*state);
+    }
     let sub_key = match optimization {
-        AESOptimization::MemoryEfficient => get_round_subkey_for_no_mem(round, &key, false),
-        _ => {
-            key.key_expansion(true);
+        AESOptimization::NoOptimization => {
+            observer.observe(
+                0,
+                RoundStep::KeySchedule,
+                &State::from_words(key.get_round_subkey(round)),
+            );
             key.get_round_subkey(round)
         }
+        AESOptimization::MemoryEfficient => round_subkey_for_no_mem(round, &key, false, spec),
+        _ => key.get_round_subkey(round),
     };
     add_round_key(&mut state, [sub_key[0], sub_key[1], sub_key[2], sub_key[3]]);
     round -= 1;
 
     while round > 0 {
+        let trace_round = num_rounds - round;
+        observer.observe(trace_round, RoundStep::Start, &state);
         match optimization {
             AESOptimization::NoOptimization => {
-                state = inv_sub_bytes(state);
+                observer.inject_fault(trace_round, RoundStep::SubBytes, &mut state);
+                state = spec_sub_bytes(state, spec, true);
+                observer.observe(trace_round, RoundStep::SubBytes, &state);
+                observer.inject_fault(trace_round, RoundStep::ShiftRows, &mut state);
                 state = inv_shift_rows(state);
-                inv_mix_columns(&mut state);
-                add_round_key(&mut state, key.get_round_subkey(round));
+                observer.observe(trace_round, RoundStep::ShiftRows, &state);
+                observer.inject_fault(trace_round, RoundStep::MixColumns, &mut state);
+                state = spec_mix_columns(state, spec, true);
+                observer.observe(trace_round, RoundStep::MixColumns, &state);
+                let round_key = key.get_round_subkey(round);
+                observer.observe(
+                    trace_round,
+                    RoundStep::KeySchedule,
+                    &State::from_words(round_key),
+                );
+                observer.inject_fault(trace_round, RoundStep::AddRoundKey, &mut state);
+                add_round_key(&mut state, round_key);
             }
             AESOptimization::MemoryEfficient => {
-                state = inv_sub_bytes_no_mem(state);
+                state = no_mem_sub_bytes(state, spec, true);
                 state = inv_shift_rows(state);
-                inv_mix_columns(&mut state);
-                let key_buffer = get_round_subkey_for_no_mem(round, &key, true);
+                state = spec_mix_columns(state, spec, true);
+                let key_buffer = round_subkey_for_no_mem(round, &key, true, spec);
                 add_round_key(
                     &mut state,
                     [key_buffer[0], key_buffer[1], key_buffer[2], key_buffer[3]],
                 );
             }
             AESOptimization::SpeedEfficient => {
-                state = merged_op_inverse(state, round, &key);
+                state = match spec {
+                    Some(spec) => merged_op_inverse_with(state, round, &key, spec.t_tables(true)),
+                    None => merged_op_inverse(state, round, &key),
+                };
+            }
+            AESOptimization::CompactSpeedEfficient => {
+                state = compact_merged_op_inverse(state, round, &key);
+            }
+            AESOptimization::Bitsliced => {
+                state = bitsliced_op_inverse(state, round, &key);
+            }
+            AESOptimization::Hardware => unreachable!(),
+            AESOptimization::VectorPermute => {
+                state = vector_permute_op_inverse(state, round, &key, backend_keys.vector_permute);
             }
         }
         round -= 1;
@@ -135,24 +266,176 @@ where
         }
         _ => key.get_round_subkey(round),
     };
+    observer.observe(num_rounds, RoundStep::Start, &state);
     match optimization {
+        AESOptimization::NoOptimization => {
+            observer.inject_fault(num_rounds, RoundStep::SubBytes, &mut state);
+            state = spec_sub_bytes(state, spec, true);
+            observer.observe(num_rounds, RoundStep::SubBytes, &state);
+            observer.inject_fault(num_rounds, RoundStep::ShiftRows, &mut state);
+            state = inv_shift_rows(state);
+            observer.observe(num_rounds, RoundStep::ShiftRows, &state);
+            observer.observe(
+                num_rounds,
+                RoundStep::KeySchedule,
+                &State::from_words(sub_key),
+            );
+            observer.inject_fault(num_rounds, RoundStep::AddRoundKey, &mut state);
+            add_round_key(&mut state, sub_key);
+        }
         AESOptimization::MemoryEfficient => {
-            state = inv_sub_bytes_no_mem(state);
+            state = no_mem_sub_bytes(state, spec, true);
+            state = inv_shift_rows(state);
+            add_round_key(&mut state, [sub_key[0], sub_key[1], sub_key[2], sub_key[3]]);
+        }
+        AESOptimization::CompactSpeedEfficient => {
+            state = compact_inv_sub_bytes(state);
+            state = inv_shift_rows(state);
+            add_round_key(&mut state, [sub_key[0], sub_key[1], sub_key[2], sub_key[3]]);
+        }
+        AESOptimization::Bitsliced => {
+            state = bitsliced_inv_sub_bytes(state);
+            state = inv_shift_rows(state);
+            add_round_key(&mut state, [sub_key[0], sub_key[1], sub_key[2], sub_key[3]]);
+        }
+        AESOptimization::Hardware => unreachable!(),
+        AESOptimization::VectorPermute => {
+            state = vector_permute_inv_sub_bytes(state, backend_keys.vector_permute);
+            state = inv_shift_rows(state);
+            add_round_key(&mut state, [sub_key[0], sub_key[1], sub_key[2], sub_key[3]]);
         }
         _ => {
-            state = inv_sub_bytes(state);
+            state = spec_sub_bytes(state, spec, true);
+            state = inv_shift_rows(state);
+            add_round_key(&mut state, [sub_key[0], sub_key[1], sub_key[2], sub_key[3]]);
         }
     }
-    state = inv_shift_rows(state);
-
-    add_round_key(&mut state, [sub_key[0], sub_key[1], sub_key[2], sub_key[3]]);
+    observer.observe(num_rounds, RoundStep::Output, &state);
 
     hex::encode(*state)
 }
 
-pub fn encrypt_block<'a, T>(message: &'a str, mut key: T, optimization: AESOptimization) -> String
+// Custom S-boxes / fields run the NoOptimization and MemoryEfficient rounds with every
+// byte computed through the field, or the SpeedEfficient rounds with generated T-tables.
+pub fn encrypt_block_with_spec<T>(
+    message: &str,
+    key: T,
+    spec: &SBoxSpec,
+    optimization: AESOptimization,
+) -> String
+where
+    T: AESKey,
+{
+    encrypt_block_rounds(
+        message,
+        key,
+        optimization,
+        &mut NoObserver,
+        Some(&SpecRounds::new(spec, optimization)),
+    )
+}
+
+pub fn decrypt_block_with_spec<T>(
+    ciphertext: &str,
+    key: T,
+    spec: &SBoxSpec,
+    optimization: AESOptimization,
+) -> String
+where
+    T: AESKey,
+{
+    decrypt_block_rounds(
+        ciphertext,
+        key,
+        optimization,
+        &mut NoObserver,
+        Some(&SpecRounds::new(spec, optimization)),
+    )
+}
+
+fn spec_key_expansion<T>(key: &mut T, spec: Option<&SpecRounds>, inverse: bool)
+where
+    T: AESKey,
+{
+    match spec {
+        Some(spec) => spec.key_expansion(key, inverse),
+        None => key.key_expansion(inverse),
+    }
+}
+
+fn spec_sub_bytes(state: State, spec: Option<&SpecRounds>, inverse: bool) -> State {
+    match (spec, inverse) {
+        (Some(spec), _) => spec.sub_bytes(state, inverse),
+        (None, false) => sub_bytes(state),
+        (None, true) => inv_sub_bytes(state),
+    }
+}
+
+fn no_mem_sub_bytes(state: State, spec: Option<&SpecRounds>, inverse: bool) -> State {
+    match (spec, inverse) {
+        (Some(spec), _) => spec.sub_bytes(state, inverse),
+        (None, false) => sub_bytes_no_mem(state),
+        (None, true) => inv_sub_bytes_no_mem(state),
+    }
+}
+
+fn spec_mix_columns(mut state: State, spec: Option<&SpecRounds>, inverse: bool) -> State {
+    match (spec, inverse) {
+        (Some(spec), false) => spec.mix_columns(state),
+        (Some(spec), true) => spec.inv_mix_columns(state),
+        (None, false) => mix_columns(state),
+        (None, true) => {
+            inv_mix_columns(&mut state);
+            state
+        }
+    }
+}
+
+fn next_key(
+    spec: Option<&SpecRounds>,
+    prev_key: [u8; 4],
+    current_key: [u8; 4],
+    key_length: usize,
+    expansion_counter: usize,
+) -> [u8; 4] {
+    match spec {
+        Some(spec) => spec.next_key(prev_key, current_key, key_length, expansion_counter),
+        None => get_next_key(prev_key, current_key, key_length, expansion_counter),
+    }
+}
+
+pub fn encrypt_block<T>(message: &str, key: T, optimization: AESOptimization) -> String
+where
+    T: AESKey,
+{
+    encrypt_block_observed(message, key, optimization, &mut NoObserver)
+}
+
+pub fn encrypt_block_observed<T, O>(
+    message: &str,
+    key: T,
+    optimization: AESOptimization,
+    observer: &mut O,
+) -> String
+where
+    T: AESKey,
+    O: RoundObserver,
+{
+    encrypt_block_rounds(message, key, optimization, observer, None)
+}
+
+// `spec` replaces the S-box, MixColumns and key schedule of the NoOptimization,
+// MemoryEfficient and SpeedEfficient rounds.
+fn encrypt_block_rounds<T, O>(
+    message: &str,
+    mut key: T,
+    optimization: AESOptimization,
+    observer: &mut O,
+    spec: Option<&SpecRounds>,
+) -> String
 where
     T: AESKey,
+    O: RoundObserver,
 {
     let message: [u8; 16] =
         decode_to_hex_vector(message)
@@ -160,88 +443,263 @@ where
             .unwrap_or_else(|v: Vec<u8>| {
                 panic!("Expected a Vec of length {} but it was {}", 16, v.len())
             });
-    let mut state = State::from_hex_vector(&message);
+    let backend_keys = expand_key(&mut key, optimization, false, spec);
+    let state = encrypt_state(
+        State::from_hex_vector(&message),
+        &key,
+        &backend_keys,
+        optimization,
+        observer,
+        spec,
+    );
+    hex::encode(*state)
+}
+
+// What `expand_key` found for the optimizations that depend on the CPU, so the
+// support is detected once per key and not in every round.
+#[derive(Clone, Debug, Default)]
+struct BackendKeys {
+    hardware: HardwareRoundKeys,
+    vector_permute: VectorPermuteSupport,
+}
 
+// The round keys each optimization needs, MemoryEfficient computes them on the fly.
+fn expand_key<T>(
+    key: &mut T,
+    optimization: AESOptimization,
+    inverse: bool,
+    spec: Option<&SpecRounds>,
+) -> BackendKeys
+where
+    T: AESKey,
+{
+    let mut backend_keys = BackendKeys::default();
     match optimization {
+        AESOptimization::NoOptimization | AESOptimization::SpeedEfficient => {
+            spec_key_expansion(key, spec, inverse)
+        }
         AESOptimization::MemoryEfficient => {}
-        _ => {
-            key.key_expansion(false);
+        AESOptimization::Bitsliced => bitsliced_key_expansion(key),
+        AESOptimization::Hardware => backend_keys.hardware = hardware_key_expansion(key, inverse),
+        AESOptimization::VectorPermute => {
+            backend_keys.vector_permute = vector_permute_key_expansion(key)
+        }
+        _ => key.key_expansion(inverse),
+    }
+    backend_keys
+}
+
+// The rounds of `encrypt_block` on a key that went through `expand_key`.
+fn encrypt_state<T, O>(
+    mut state: State,
+    key: &T,
+    backend_keys: &BackendKeys,
+    optimization: AESOptimization,
+    observer: &mut O,
+    spec: Option<&SpecRounds>,
+) -> State
+where
+    T: AESKey,
+    O: RoundObserver,
+{
+    check_fault_injection(observer, optimization);
+    observer.begin(false);
+    observer.observe(0, RoundStep::Input, &state);
+    if optimization == AESOptimization::Hardware {
+        state = hardware_encrypt_state(state, key, &backend_keys.hardware);
+        observer.observe(key.num_rounds(), RoundStep::Output, &state);
+        return state;
+    }
+
+    match optimization {
+        AESOptimization::NoOptimization => {
+            observer.observe(
+                0,
+                RoundStep::KeySchedule,
+                &State::from_words(key.get_round_subkey(0)),
+            );
             add_round_key(&mut state, key.get_round_subkey(0));
         }
+        AESOptimization::MemoryEfficient => {}
+        _ => add_round_key(&mut state, key.get_round_subkey(0)),
     }
 
     let mut expansion_counter = key.key_length();
     let mut key_buffer = key.get_key();
 
-    match optimization {
-        AESOptimization::MemoryEfficient => {
-            for c in 0..4 {
-                state[4 * c + 0] ^= key_buffer[c][0];
-                state[4 * c + 1] ^= key_buffer[c][1];
-                state[4 * c + 2] ^= key_buffer[c][2];
-                state[4 * c + 3] ^= key_buffer[c][3];
-            }
+    if optimization == AESOptimization::MemoryEfficient {
+        for c in 0..4 {
+            state[4 * c] ^= key_buffer[c][0];
+            state[4 * c + 1] ^= key_buffer[c][1];
+            state[4 * c + 2] ^= key_buffer[c][2];
+            state[4 * c + 3] ^= key_buffer[c][3];
         }
-        _ => {}
     }
 
     for round in 1..key.num_rounds() {
+        observer.observe(round, RoundStep::Start, &state);
         match optimization {
             AESOptimization::NoOptimization => {
-                state = sub_bytes(state);
+                observer.inject_fault(round, RoundStep::SubBytes, &mut state);
+                state = spec_sub_bytes(state, spec, false);
+                observer.observe(round, RoundStep::SubBytes, &state);
+                observer.inject_fault(round, RoundStep::ShiftRows, &mut state);
                 state = sub_rows(state);
-                state = mix_columns(state);
-                add_round_key(&mut state, key.get_round_subkey(round));
+                observer.observe(round, RoundStep::ShiftRows, &state);
+                observer.inject_fault(round, RoundStep::MixColumns, &mut state);
+                state = spec_mix_columns(state, spec, false);
+                observer.observe(round, RoundStep::MixColumns, &state);
+                let round_key = key.get_round_subkey(round);
+                observer.observe(round, RoundStep::KeySchedule, &State::from_words(round_key));
+                observer.inject_fault(round, RoundStep::AddRoundKey, &mut state);
+                add_round_key(&mut state, round_key);
             }
             AESOptimization::MemoryEfficient => {
-                state = sub_bytes_no_mem(state);
+                state = no_mem_sub_bytes(state, spec, false);
                 state = sub_rows(state);
-                state = mix_columns(state);
+                state = spec_mix_columns(state, spec, false);
 
                 let (new_state, new_expansion_counter) = add_round_key_no_mem(
                     state,
                     &mut key_buffer,
                     expansion_counter,
                     key.key_length(),
+                    |prev, current, length, counter| next_key(spec, prev, current, length, counter),
                 );
                 state = new_state;
 
                 expansion_counter = new_expansion_counter;
             }
             AESOptimization::SpeedEfficient => {
-                state = merged_op(state, round, &key);
+                state = match spec {
+                    Some(spec) => merged_op_with(state, round, key, spec.t_tables(false)),
+                    None => merged_op(state, round, key),
+                };
+            }
+            AESOptimization::CompactSpeedEfficient => {
+                state = compact_merged_op(state, round, key);
+            }
+            AESOptimization::Bitsliced => {
+                state = bitsliced_op(state, round, key);
+            }
+            AESOptimization::Hardware => unreachable!(),
+            AESOptimization::VectorPermute => {
+                state = vector_permute_op(state, round, key, backend_keys.vector_permute);
             }
         }
     }
+    observer.observe(key.num_rounds(), RoundStep::Start, &state);
     match optimization {
+        AESOptimization::NoOptimization => {
+            let round_key = key.get_round_subkey(key.num_rounds());
+            observer.inject_fault(key.num_rounds(), RoundStep::SubBytes, &mut state);
+            state = spec_sub_bytes(state, spec, false);
+            observer.observe(key.num_rounds(), RoundStep::SubBytes, &state);
+            observer.inject_fault(key.num_rounds(), RoundStep::ShiftRows, &mut state);
+            state = sub_rows(state);
+            observer.observe(key.num_rounds(), RoundStep::ShiftRows, &state);
+            observer.observe(
+                key.num_rounds(),
+                RoundStep::KeySchedule,
+                &State::from_words(round_key),
+            );
+            observer.inject_fault(key.num_rounds(), RoundStep::AddRoundKey, &mut state);
+            add_round_key(&mut state, round_key);
+        }
         AESOptimization::MemoryEfficient => {
-            state = sub_bytes_no_mem(state);
+            state = no_mem_sub_bytes(state, spec, false);
             state = sub_rows(state);
-            let (new_state, _) =
-                add_round_key_no_mem(state, &mut key_buffer, expansion_counter, key.key_length());
+            let (new_state, _) = add_round_key_no_mem(
+                state,
+                &mut key_buffer,
+                expansion_counter,
+                key.key_length(),
+                |prev, current, length, counter| next_key(spec, prev, current, length, counter),
+            );
             state = new_state;
         }
+        AESOptimization::CompactSpeedEfficient => {
+            state = compact_sub_bytes(state);
+            state = sub_rows(state);
+            add_round_key(&mut state, key.get_round_subkey(key.num_rounds()));
+        }
+        AESOptimization::Bitsliced => {
+            state = bitsliced_sub_bytes(state);
+            state = sub_rows(state);
+            add_round_key(&mut state, key.get_round_subkey(key.num_rounds()));
+        }
+        AESOptimization::Hardware => unreachable!(),
+        AESOptimization::VectorPermute => {
+            state = vector_permute_sub_bytes(state, backend_keys.vector_permute);
+            state = sub_rows(state);
+            add_round_key(&mut state, key.get_round_subkey(key.num_rounds()));
+        }
         _ => {
-            state = sub_bytes(state);
+            state = spec_sub_bytes(state, spec, false);
             state = sub_rows(state);
             add_round_key(&mut state, key.get_round_subkey(key.num_rounds()));
         }
     }
-
-    hex::encode(*state)
+    observer.observe(key.num_rounds(), RoundStep::Output, &state);
+    state
 }
 
+#[cfg(test)]
 #[cfg(test)]
 mod tests {
 
     use crate::common::{
-        cipher_operations::{inv_sub_byte_no_mem, mult, sbox_no_mem},
-        key::{KeyNk4, KeyNk6, KeyNk8},
-        modes::encrypt_ecb,
+        aes_ni::{
+            hardware_decrypt_blocks, hardware_encrypt_blocks, is_hardware_supported,
+            set_hardware_disabled,
+        },
+        cipher_operations::{
+            inv_sub_byte, inv_sub_byte_no_mem, inverse_with, mult, mult_with, sbox_no_mem, sub_byte,
+        },
+        ctr_drbg::{CtrDrbg, CtrDrbgError, RngCore, MAX_BYTES_PER_REQUEST},
+        gf256::Gf256,
+        key::{KeyNk4, KeyNk5, KeyNk6, KeyNk7, KeyNk8},
+        modes::{
+            decrypt_ctr, decrypt_xts, decrypt_xts_with_optimization, encrypt_ctr,
+            encrypt_ctr_with_optimization, encrypt_ecb, encrypt_ecb_with_optimization, encrypt_xts,
+            encrypt_xts_with_optimization,
+        },
         padding::{pad_message_pkcs7, unpad_message_pkcs7},
+        rijndael::{rijndael_num_rounds, Rijndael},
+        sbox::{INVERSE_SBOX_TABLE, SBOX_TABLE},
+        sbox_spec::{is_irreducible, FieldParams},
+        t_tables::{
+            FINAL_ROUND_INVERSE_SBOX, FINAL_ROUND_SBOX, T0, T1, T2, T3, TD0, TD1, TD2, TD3,
+        },
+        trace::Trace,
+        trace_export::{trace_to_html, trace_to_json},
     };
 
     use super::*;
+    use crate::cryptanalysis::{
+        algebraic::{sbox_relations, AlgebraicModel},
+        cpa::{cpa_attack_set, evaluate_cpa, pearson_correlation},
+        dfa::{fault_column, recover_last_round_key, FaultInjector},
+        inverse_key_schedule::{recover_key_schedule, recover_master_key},
+        key_scanner::KeyScanner,
+        masking::{masked_sbox_table, MaskedAES, MaskedState},
+        power_trace::{
+            plaintexts_to_npy, traces_to_npy, write_traces_npy, LeakageModel, PowerTraceSimulator,
+            SimulationRng,
+        },
+        reduced_round::ReducedRoundAES,
+        related_key::{key_pair_difference, propagate_key_difference, ByteDifference},
+        sbox_analysis::{
+            aes_sbox, analyse_sbox, difference_distribution_table, linear_approximation_table,
+            table_to_csv,
+        },
+        small_scale::SmallScaleAES,
+        square::{balanced_key_bytes, encrypt_lambda_set, lambda_set, square_attack},
+        truncated::{
+            branch_number, is_mds, min_active_sboxes, mix_columns_matrix, mix_columns_transitions,
+            pattern_to_string, shift_rows_pattern,
+        },
+    };
 
     #[test]
     fn it_mults() {
@@ -278,6 +736,145 @@ mod tests {
         let result = inv_sub_byte_no_mem(result);
         assert_eq!(b, result);
     }
+    #[test]
+    fn it_generates_tables_from_field_arithmetic() {
+        assert_eq!(
+            SBOX_TABLE[0],
+            [
+                0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7,
+                0xab, 0x76
+            ]
+        );
+        assert_eq!(
+            INVERSE_SBOX_TABLE[0],
+            [
+                0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38, 0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3,
+                0xd7, 0xfb
+            ]
+        );
+
+        for byte in 0..=255u8 {
+            let s = SBOX_TABLE[(byte >> 4) as usize][(byte & 0x0f) as usize];
+            let inv_s = INVERSE_SBOX_TABLE[(byte >> 4) as usize][(byte & 0x0f) as usize];
+            assert_eq!(s, sbox_no_mem(byte));
+            assert_eq!(inv_s, inv_sub_byte_no_mem(byte));
+            assert_eq!(inv_sub_byte(s), byte);
+
+            let column = [mult(s, 0x02), s, s, mult(s, 0x03)];
+            assert_eq!(T0[byte as usize], column);
+            assert_eq!(
+                T1[byte as usize],
+                [column[3], column[0], column[1], column[2]]
+            );
+            assert_eq!(
+                T2[byte as usize],
+                [column[2], column[3], column[0], column[1]]
+            );
+            assert_eq!(
+                T3[byte as usize],
+                [column[1], column[2], column[3], column[0]]
+            );
+
+            let column = [
+                mult(inv_s, 0x0e),
+                mult(inv_s, 0x09),
+                mult(inv_s, 0x0d),
+                mult(inv_s, 0x0b),
+            ];
+            assert_eq!(TD0[byte as usize], column);
+            assert_eq!(
+                TD1[byte as usize],
+                [column[3], column[0], column[1], column[2]]
+            );
+            assert_eq!(
+                TD2[byte as usize],
+                [column[2], column[3], column[0], column[1]]
+            );
+            assert_eq!(
+                TD3[byte as usize],
+                [column[1], column[2], column[3], column[0]]
+            );
+        }
+    }
+
+    #[test]
+    fn it_bitsliced_sub_bytes_matches_sbox_table() {
+        for chunk in (0..=255u8).collect::<Vec<u8>>().chunks(16) {
+            let state = State::from_hex_vector(chunk.try_into().unwrap());
+
+            let result = bitsliced_sub_bytes(state);
+            for (i, byte) in chunk.iter().enumerate() {
+                assert_eq!(result[i], sub_byte(*byte));
+            }
+
+            let result = bitsliced_inv_sub_bytes(state);
+            for (i, byte) in chunk.iter().enumerate() {
+                assert_eq!(result[i], inv_sub_byte(*byte));
+            }
+        }
+    }
+    #[test]
+    fn it_vector_permute_sub_bytes_matches_sbox_table() {
+        let detected =
+            vector_permute_key_expansion(&mut KeyNk4::new("000102030405060708090a0b0c0d0e0f"));
+        for support in [detected, VectorPermuteSupport::default()] {
+            for chunk in (0..=255u8).collect::<Vec<u8>>().chunks(16) {
+                let state = State::from_hex_vector(chunk.try_into().unwrap());
+
+                let result = vector_permute_sub_bytes(state, support);
+                for (i, byte) in chunk.iter().enumerate() {
+                    assert_eq!(result[i], sub_byte(*byte));
+                }
+
+                let result = vector_permute_inv_sub_bytes(state, support);
+                for (i, byte) in chunk.iter().enumerate() {
+                    assert_eq!(result[i], inv_sub_byte(*byte));
+                }
+            }
+        }
+    }
+
+    fn next_random_hex(seed: &mut u64, bytes: usize) -> String {
+        let mut data = Vec::with_capacity(bytes);
+        for _ in 0..bytes {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            data.push((*seed >> 32) as u8);
+        }
+        hex::encode(data)
+    }
+
+    #[test]
+    fn it_matches_reference_with_vector_permute_on_random_inputs() {
+        let mut seed = 0x243f6a8885a308d3;
+
+        for _ in 0..64 {
+            let message = next_random_hex(&mut seed, 16);
+
+            let key = KeyNk4::new(&next_random_hex(&mut seed, 16));
+            let ciphertext = encrypt_block(&message, key, AESOptimization::NoOptimization);
+            let result = encrypt_block(&message, key, AESOptimization::VectorPermute);
+            assert_eq!(result, ciphertext);
+            let result = decrypt_block(&ciphertext, key, AESOptimization::VectorPermute);
+            assert_eq!(result, message);
+
+            let key = KeyNk6::new(&next_random_hex(&mut seed, 24));
+            let ciphertext = encrypt_block(&message, key, AESOptimization::NoOptimization);
+            let result = encrypt_block(&message, key, AESOptimization::VectorPermute);
+            assert_eq!(result, ciphertext);
+            let result = decrypt_block(&ciphertext, key, AESOptimization::VectorPermute);
+            assert_eq!(result, message);
+
+            let key = KeyNk8::new(&next_random_hex(&mut seed, 32));
+            let ciphertext = encrypt_block(&message, key, AESOptimization::NoOptimization);
+            let result = encrypt_block(&message, key, AESOptimization::VectorPermute);
+            assert_eq!(result, ciphertext);
+            let result = decrypt_block(&ciphertext, key, AESOptimization::VectorPermute);
+            assert_eq!(result, message);
+        }
+    }
+
     #[test]
     fn it_encrypts_message_128_key() {
         let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
@@ -300,12 +897,48 @@ mod tests {
 
         let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
 
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::CompactSpeedEfficient,
+        );
+        assert_eq!(result, "69c4e0d86a7b0430d8cdb78070b4c55a");
+
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+
         let result = encrypt_block(
             "00112233445566778899aabbccddeeff",
             key,
             AESOptimization::MemoryEfficient,
         );
         assert_eq!(result, "69c4e0d86a7b0430d8cdb78070b4c55a");
+
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::Bitsliced,
+        );
+        assert_eq!(result, "69c4e0d86a7b0430d8cdb78070b4c55a");
+
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::Hardware,
+        );
+        assert_eq!(result, "69c4e0d86a7b0430d8cdb78070b4c55a");
+
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::VectorPermute,
+        );
+        assert_eq!(result, "69c4e0d86a7b0430d8cdb78070b4c55a");
     }
 
     #[test]
@@ -330,12 +963,48 @@ mod tests {
 
         let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
 
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::CompactSpeedEfficient,
+        );
+        assert_eq!(result, "dda97ca4864cdfe06eaf70a0ec0d7191");
+
+        let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
+
         let result = encrypt_block(
             "00112233445566778899aabbccddeeff",
             key,
             AESOptimization::MemoryEfficient,
         );
         assert_eq!(result, "dda97ca4864cdfe06eaf70a0ec0d7191");
+
+        let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::Bitsliced,
+        );
+        assert_eq!(result, "dda97ca4864cdfe06eaf70a0ec0d7191");
+
+        let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::Hardware,
+        );
+        assert_eq!(result, "dda97ca4864cdfe06eaf70a0ec0d7191");
+
+        let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::VectorPermute,
+        );
+        assert_eq!(result, "dda97ca4864cdfe06eaf70a0ec0d7191");
     }
 
     #[test]
@@ -366,32 +1035,99 @@ mod tests {
             AESOptimization::SpeedEfficient,
         );
         assert_eq!(result, "8ea2b7ca516745bfeafc49904b496089");
-    }
 
-    #[test]
-    fn it_decrypts_message_128_key() {
-        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
-        let result = decrypt_block(
-            "69c4e0d86a7b0430d8cdb78070b4c55a",
-            key,
-            AESOptimization::NoOptimization,
-        );
-        assert_eq!(result, "00112233445566778899aabbccddeeff");
-        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
-        let result = decrypt_block(
-            "69c4e0d86a7b0430d8cdb78070b4c55a",
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
             key,
-            AESOptimization::SpeedEfficient,
+            AESOptimization::CompactSpeedEfficient,
         );
-        assert_eq!(result, "00112233445566778899aabbccddeeff");
-        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
-        let result = decrypt_block(
-            "69c4e0d86a7b0430d8cdb78070b4c55a",
+        assert_eq!(result, "8ea2b7ca516745bfeafc49904b496089");
+
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
             key,
-            AESOptimization::MemoryEfficient,
+            AESOptimization::Bitsliced,
         );
-        assert_eq!(result, "00112233445566778899aabbccddeeff");
-    }
+        assert_eq!(result, "8ea2b7ca516745bfeafc49904b496089");
+
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::Hardware,
+        );
+        assert_eq!(result, "8ea2b7ca516745bfeafc49904b496089");
+
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+
+        let result = encrypt_block(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::VectorPermute,
+        );
+        assert_eq!(result, "8ea2b7ca516745bfeafc49904b496089");
+    }
+
+    #[test]
+    fn it_decrypts_message_128_key() {
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let result = decrypt_block(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::NoOptimization,
+        );
+        assert_eq!(result, "00112233445566778899aabbccddeeff");
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let result = decrypt_block(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::SpeedEfficient,
+        );
+        assert_eq!(result, "00112233445566778899aabbccddeeff");
+
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let result = decrypt_block(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::CompactSpeedEfficient,
+        );
+        assert_eq!(result, "00112233445566778899aabbccddeeff");
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let result = decrypt_block(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::MemoryEfficient,
+        );
+        assert_eq!(result, "00112233445566778899aabbccddeeff");
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let result = decrypt_block(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::Bitsliced,
+        );
+        assert_eq!(result, "00112233445566778899aabbccddeeff");
+
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let result = decrypt_block(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::Hardware,
+        );
+        assert_eq!(result, "00112233445566778899aabbccddeeff");
+
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let result = decrypt_block(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::VectorPermute,
+        );
+        assert_eq!(result, "00112233445566778899aabbccddeeff");
+    }
     #[test]
     fn it_decrypts_message_192_key() {
         let ciphertext = "dda97ca4864cdfe06eaf70a0ec0d7191";
@@ -409,6 +1145,25 @@ mod tests {
 
         let result = decrypt_block(ciphertext, key, AESOptimization::SpeedEfficient);
         assert_eq!(result, expected_plaintext);
+
+        let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
+
+        let result = decrypt_block(ciphertext, key, AESOptimization::CompactSpeedEfficient);
+        assert_eq!(result, expected_plaintext);
+        let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
+
+        let result = decrypt_block(ciphertext, key, AESOptimization::Bitsliced);
+        assert_eq!(result, expected_plaintext);
+
+        let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
+
+        let result = decrypt_block(ciphertext, key, AESOptimization::Hardware);
+        assert_eq!(result, expected_plaintext);
+
+        let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
+
+        let result = decrypt_block(ciphertext, key, AESOptimization::VectorPermute);
+        assert_eq!(result, expected_plaintext);
     }
     #[test]
     fn it_decrypts_message_256_key() {
@@ -423,11 +1178,1566 @@ mod tests {
         let result = decrypt_block::<KeyNk8>(ciphertext, key, AESOptimization::SpeedEfficient);
         assert_eq!(result, expected_plaintext);
 
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let result =
+            decrypt_block::<KeyNk8>(ciphertext, key, AESOptimization::CompactSpeedEfficient);
+        assert_eq!(result, expected_plaintext);
+
         let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
         let result = decrypt_block::<KeyNk8>(ciphertext, key, AESOptimization::MemoryEfficient);
         assert_eq!(result, expected_plaintext);
+
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let result = decrypt_block::<KeyNk8>(ciphertext, key, AESOptimization::Bitsliced);
+        assert_eq!(result, expected_plaintext);
+
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let result = decrypt_block::<KeyNk8>(ciphertext, key, AESOptimization::Hardware);
+        assert_eq!(result, expected_plaintext);
+
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let result = decrypt_block::<KeyNk8>(ciphertext, key, AESOptimization::VectorPermute);
+        assert_eq!(result, expected_plaintext);
     }
 
+    #[test]
+    fn it_encrypts_ctr_with_counter_wrap() {
+        let key = KeyNk4::new("2b7e151628aed2a6abf7158809cf4f3c");
+        let message = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51";
+        let counter = "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff";
+        let expected_ciphertext =
+            "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff";
+        for optimization in [AESOptimization::NoOptimization, AESOptimization::Hardware] {
+            let ciphertext = encrypt_ctr_with_optimization(message, key, counter, optimization);
+            assert_eq!(expected_ciphertext, ciphertext);
+        }
+        assert_eq!(message, decrypt_ctr(expected_ciphertext, key, counter));
+
+        let message = "00".repeat(40);
+        let counter = "ff".repeat(16);
+        let expected_ciphertext =
+            "8af2860142f786f409307c1a3f7eaaac7df76b0c1ab899b33e42f047b91b546f57127d4034b1bebf";
+        assert_eq!(expected_ciphertext, encrypt_ctr(&message, key, &counter));
+        let ciphertext =
+            encrypt_ctr_with_optimization(&message, key, &counter, AESOptimization::Hardware);
+        assert_eq!(expected_ciphertext, ciphertext);
+    }
+
+    #[test]
+    fn it_encrypts_and_decrypts_xts() {
+        let tweak = "33333333330000000000000000000000";
+        let message = "44".repeat(32);
+        let data_key = KeyNk4::new(&"11".repeat(16));
+        let tweak_key = KeyNk4::new(&"22".repeat(16));
+        let expected_ciphertext =
+            "c454185e6a16936e39334038acef838bfb186fff7480adc4289382ecd6d394f0";
+        for optimization in [AESOptimization::NoOptimization, AESOptimization::Hardware] {
+            let ciphertext =
+                encrypt_xts_with_optimization(&message, data_key, tweak_key, tweak, optimization);
+            assert_eq!(expected_ciphertext, ciphertext);
+            let plaintext = decrypt_xts_with_optimization(
+                expected_ciphertext,
+                data_key,
+                tweak_key,
+                tweak,
+                optimization,
+            );
+            assert_eq!(message, plaintext);
+        }
+
+        let data_key = KeyNk8::new(&"11".repeat(32));
+        let tweak_key = KeyNk8::new(&"22".repeat(32));
+        let expected_ciphertext =
+            "e622334f184bbce129a25b2ac76b3d92abf98e22df5bdd15af471f3db8946a85";
+        assert_eq!(
+            expected_ciphertext,
+            encrypt_xts(&message, data_key, tweak_key, tweak)
+        );
+        assert_eq!(
+            message,
+            decrypt_xts(expected_ciphertext, data_key, tweak_key, tweak)
+        );
+    }
+
+    #[test]
+    fn it_encrypts_xts_with_ciphertext_stealing() {
+        let data_key = KeyNk4::new("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0");
+        let tweak_key = KeyNk4::new("bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0");
+        let tweak = "9a785634120000000000000000000000";
+        let vectors = [
+            (
+                "000102030405060708090a0b0c0d0e0f10",
+                "6c1625db4671522d3d7599601de7ca09ed",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20212223242526",
+                "edbf9dace45d6f6a7306e64be5dd824b025de0f8f4abe2b5c0459cd2cbe8f2f82538f5724fcf24",
+            ),
+        ];
+        for (message, expected_ciphertext) in vectors {
+            for optimization in [AESOptimization::NoOptimization, AESOptimization::Hardware] {
+                let ciphertext = encrypt_xts_with_optimization(
+                    message,
+                    data_key,
+                    tweak_key,
+                    tweak,
+                    optimization,
+                );
+                assert_eq!(expected_ciphertext, ciphertext);
+                let plaintext = decrypt_xts_with_optimization(
+                    expected_ciphertext,
+                    data_key,
+                    tweak_key,
+                    tweak,
+                    optimization,
+                );
+                assert_eq!(message, plaintext);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected at least 16 bytes for XTS but it was 15")]
+    fn it_rejects_short_xts_messages() {
+        let key = KeyNk4::new("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0");
+        encrypt_xts(&"00".repeat(15), key, key, &"00".repeat(16));
+    }
+
+    #[test]
+    fn it_falls_back_when_hardware_is_disabled() {
+        set_hardware_disabled(true);
+        assert!(!is_hardware_supported());
+
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let message = "00112233445566778899aabbccddeeff";
+        let ciphertext = encrypt_block(message, key, AESOptimization::Hardware);
+        assert_eq!("69c4e0d86a7b0430d8cdb78070b4c55a", ciphertext);
+        assert_eq!(
+            message,
+            decrypt_block(&ciphertext, key, AESOptimization::Hardware)
+        );
+
+        let key = KeyNk4::new("2b7e151628aed2a6abf7158809cf4f3c");
+        let mut blocks: Vec<[u8; 16]> = (0..9u8).map(|i| [i; 16]).collect();
+        let plaintext = blocks.clone();
+        hardware_encrypt_blocks(&mut blocks, &mut key.clone());
+        for (block, message) in blocks.iter().zip(plaintext.iter()) {
+            let expected =
+                encrypt_block(&hex::encode(message), key, AESOptimization::NoOptimization);
+            assert_eq!(hex::encode(block), expected);
+        }
+        hardware_decrypt_blocks(&mut blocks, &mut key.clone());
+        assert_eq!(blocks, plaintext);
+
+        let ciphertext = encrypt_ctr_with_optimization(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51",
+            key,
+            "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff",
+            AESOptimization::Hardware,
+        );
+        assert_eq!(
+            "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff",
+            ciphertext
+        );
+
+        let ciphertext = encrypt_xts_with_optimization(
+            &"44".repeat(32),
+            KeyNk4::new(&"11".repeat(16)),
+            KeyNk4::new(&"22".repeat(16)),
+            "33333333330000000000000000000000",
+            AESOptimization::Hardware,
+        );
+        assert_eq!(
+            "c454185e6a16936e39334038acef838bfb186fff7480adc4289382ecd6d394f0",
+            ciphertext
+        );
+        set_hardware_disabled(false);
+    }
+    #[test]
+    fn it_encrypts_and_decrypts_compact_rounds_like_the_reference() {
+        for byte in 0..=255u8 {
+            let (row, column) = ((byte >> 4) as usize, (byte & 0x0f) as usize);
+            assert_eq!(FINAL_ROUND_SBOX[byte as usize], SBOX_TABLE[row][column]);
+            assert_eq!(
+                FINAL_ROUND_INVERSE_SBOX[byte as usize],
+                INVERSE_SBOX_TABLE[row][column]
+            );
+        }
+
+        let mut seed = 0xda942042e4dd58b5;
+        let mut key = KeyNk6::new(&next_random_hex(&mut seed, 24));
+        let mut inverse_key = key;
+        key.key_expansion(false);
+        inverse_key.key_expansion(true);
+        for round in 1..key.num_rounds() {
+            let input: [u8; 16] = hex::decode(next_random_hex(&mut seed, 16))
+                .unwrap()
+                .try_into()
+                .unwrap();
+            let state = State::from_hex_vector(&input);
+
+            let mut expected = mix_columns(sub_rows(sub_bytes(state)));
+            add_round_key(&mut expected, key.get_round_subkey(round));
+            assert_eq!(*compact_merged_op(state, round, &key), *expected);
+
+            let mut expected = inv_shift_rows(inv_sub_bytes(state));
+            inv_mix_columns(&mut expected);
+            add_round_key(&mut expected, inverse_key.get_round_subkey(round));
+            assert_eq!(
+                *compact_merged_op_inverse(state, round, &inverse_key),
+                *expected
+            );
+
+            assert_eq!(*compact_sub_bytes(state), *sub_bytes(state));
+            assert_eq!(*compact_inv_sub_bytes(state), *inv_sub_bytes(state));
+        }
+
+        let message = next_random_hex(&mut seed, 16);
+        let ciphertext = encrypt_block(&message, key, AESOptimization::CompactSpeedEfficient);
+        assert_eq!(
+            ciphertext,
+            encrypt_block(&message, key, AESOptimization::NoOptimization)
+        );
+        assert_eq!(
+            decrypt_block(&ciphertext, key, AESOptimization::CompactSpeedEfficient),
+            message
+        );
+    }
+
+    #[test]
+    fn it_traces_encryption_like_fips_197_appendix_c() {
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let mut trace = Trace::new();
+        let result = encrypt_block_observed(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::NoOptimization,
+            &mut trace,
+        );
+        assert_eq!(result, "69c4e0d86a7b0430d8cdb78070b4c55a");
+
+        let lines = trace.to_fips_string();
+        assert!(lines.starts_with(
+            "round[ 0].input   00112233445566778899aabbccddeeff\n\
+             round[ 0].k_sch   000102030405060708090a0b0c0d0e0f\n\
+             round[ 1].start   00102030405060708090a0b0c0d0e0f0\n\
+             round[ 1].s_box   63cab7040953d051cd60e0e7ba70e18c\n\
+             round[ 1].s_row   6353e08c0960e104cd70b751bacad0e7\n\
+             round[ 1].m_col   5f72641557f5bc92f7be3b291db9f91a\n\
+             round[ 1].k_sch   d6aa74fdd2af72fadaa678f1d6ab76fe\n"
+        ));
+        assert!(lines.ends_with(
+            "round[10].start   bd6e7c3df2b5779e0b61216e8b10b689\n\
+             round[10].s_box   7a9f102789d5f50b2beffd9f3dca4ea7\n\
+             round[10].s_row   7ad5fda789ef4e272bca100b3d9ff59f\n\
+             round[10].k_sch   13111d7fe3944a17f307a78b4d2b30c5\n\
+             round[10].output  69c4e0d86a7b0430d8cdb78070b4c55a\n"
+        ));
+        assert_eq!(trace.entries.len(), 2 + 9 * 5 + 4 + 1);
+        assert_eq!(
+            hex::encode(**trace.get(5, RoundStep::Start).unwrap()),
+            "247240236966b3fa6ed2753288425b6c"
+        );
+    }
+    #[test]
+    fn it_traces_decryption_with_the_equivalent_inverse_cipher() {
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let mut trace = Trace::new();
+        let result = decrypt_block_observed(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::NoOptimization,
+            &mut trace,
+        );
+        assert_eq!(result, "00112233445566778899aabbccddeeff");
+
+        // The Equivalent Inverse Cipher rows of FIPS-197 Appendix C.1.
+        let lines = trace.to_fips_string();
+        assert!(lines.starts_with(
+            "round[ 0].iinput  69c4e0d86a7b0430d8cdb78070b4c55a\n\
+             round[ 0].ik_sch  13111d7fe3944a17f307a78b4d2b30c5\n\
+             round[ 1].istart  7ad5fda789ef4e272bca100b3d9ff59f\n\
+             round[ 1].is_box  bdb52189f261b63d0b107c9e8b6e776e\n\
+             round[ 1].is_row  bd6e7c3df2b5779e0b61216e8b10b689\n\
+             round[ 1].im_col  4773b91ff72f354361cb018ea1e6cf2c\n\
+             round[ 1].ik_sch  13aa29be9c8faff6f770f58000f7bf03\n\
+             round[ 2].istart  54d990a16ba09ab596bbf40ea111702f\n"
+        ));
+        assert!(lines.ends_with(
+            "round[10].istart  6353e08c0960e104cd70b751bacad0e7\n\
+             round[10].is_box  0050a0f04090e03080d02070c01060b0\n\
+             round[10].is_row  00102030405060708090a0b0c0d0e0f0\n\
+             round[10].ik_sch  000102030405060708090a0b0c0d0e0f\n\
+             round[10].ioutput 00112233445566778899aabbccddeeff\n"
+        ));
+        assert_eq!(trace.entries.len(), 2 + 9 * 5 + 4 + 1);
+
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let mut trace = Trace::new();
+        decrypt_block_observed(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::SpeedEfficient,
+            &mut trace,
+        );
+        assert_eq!(
+            hex::encode(**trace.get(1, RoundStep::Start).unwrap()),
+            "7ad5fda789ef4e272bca100b3d9ff59f"
+        );
+        assert!(trace.get(1, RoundStep::SubBytes).is_none());
+    }
+    #[test]
+    fn it_exports_trace_to_json_and_html() {
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let mut trace = Trace::new();
+        trace.record_key_schedule(key);
+        encrypt_block_observed(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::NoOptimization,
+            &mut trace,
+        );
+        assert_eq!(trace.key_schedule.len(), 44);
+        assert_eq!(hex::encode(trace.key_schedule[43]), "4d2b30c5");
+
+        let json = trace_to_json(&trace);
+        assert!(json.starts_with("{\"inverse\":false,\"entries\":[{\"round\":0,\"step\":\"input\""));
+        assert!(json.contains(
+            "{\"round\":1,\"step\":\"s_box\",\"state\":\"63cab7040953d051cd60e0e7ba70e18c\""
+        ));
+        assert!(json.ends_with("\"13111d7f\",\"e3944a17\",\"f307a78b\",\"4d2b30c5\"]}"));
+
+        let html = trace_to_html(&trace);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(!html.contains("<script") && !html.contains("http"));
+        assert!(html.contains("<td class=\"changed\">63</td>"));
+        assert!(html.contains("<tr><td>w[43]</td><td>4d2b30c5</td></tr>"));
+        assert_eq!(
+            html.matches("<table class=\"state\">").count(),
+            trace.entries.len()
+        );
+    }
+    #[test]
+    fn it_encrypts_with_reduced_rounds() {
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let cipher = ReducedRoundAES::new(&key, 10, false);
+        assert_eq!(
+            cipher.encrypt_block("00112233445566778899aabbccddeeff"),
+            "69c4e0d86a7b0430d8cdb78070b4c55a"
+        );
+
+        // One full round is round[ 2].start of FIPS-197 Appendix C.1.
+        let cipher = ReducedRoundAES::new(&key, 1, true);
+        assert_eq!(
+            cipher.encrypt_block("00112233445566778899aabbccddeeff"),
+            "89d810e8855ace682d1843d8cb128fe4"
+        );
+
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let cipher = ReducedRoundAES::new(&key, 14, false);
+        assert_eq!(
+            cipher.encrypt_block("00112233445566778899aabbccddeeff"),
+            "8ea2b7ca516745bfeafc49904b496089"
+        );
+
+        let mut seed = 0x2545f4914f6cdd1d;
+        for rounds in 1..=14 {
+            for final_mix_columns in [false, true] {
+                let key = KeyNk4::new(&next_random_hex(&mut seed, 16));
+                let cipher = ReducedRoundAES::new(&key, rounds, final_mix_columns);
+                let message = next_random_hex(&mut seed, 16);
+                let ciphertext = cipher.encrypt_block(&message);
+                assert_eq!(cipher.decrypt_block(&ciphertext), message);
+            }
+        }
+    }
+    #[test]
+    #[should_panic(expected = "Expected between 1 and 14 rounds but it was 15")]
+    fn it_rejects_too_many_rounds() {
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        ReducedRoundAES::new(&key, 15, false);
+    }
+    #[test]
+    fn it_uses_small_scale_sbox_of_sage() {
+        let sr = SmallScaleAES::new(1, 1, 1, 4, false);
+        let sbox: Vec<u8> = (0..16).map(|x| sr.sbox(x)).collect();
+        assert_eq!(sbox, [6, 11, 5, 4, 2, 14, 7, 10, 9, 13, 15, 12, 3, 1, 0, 8]);
+        for x in 0..16 {
+            assert_eq!(sr.inverse_sbox(sr.sbox(x)), x);
+            if x != 0 {
+                assert_eq!(sr.mult(x, sr.inverse(x)), 1);
+            }
+        }
+    }
+    #[test]
+    fn it_encrypts_small_scale_aes() {
+        // SR*(10, 4, 4, 8) is AES-128.
+        let sr = SmallScaleAES::new(10, 4, 4, 8, true);
+        assert_eq!(
+            sr.encrypt_block(
+                "00112233445566778899aabbccddeeff",
+                "000102030405060708090a0b0c0d0e0f"
+            ),
+            "69c4e0d86a7b0430d8cdb78070b4c55a"
+        );
+        // The examples of `SR.__call__` in Sage, mq.SR(10, 4, 4, 8, star=True).
+        assert_eq!(
+            sr.encrypt_block(
+                "000102030405060708090a0b0c0d0e0f",
+                "000102030405060708090a0b0c0d0e0f"
+            ),
+            "0a940bb5416ef045f1c39458c653ea5a"
+        );
+        assert_eq!(
+            sr.encrypt_block(
+                "3243f6a8885a308d313198a2e0370734",
+                "2b7e151628aed2a6abf7158809cf4f3c"
+            ),
+            "3925841d02dc09fbdc118597196a0b32"
+        );
+
+        let mut seed = 0x9e3779b97f4a7c15;
+        for rounds in 1..=10 {
+            let key = next_random_hex(&mut seed, 16);
+            let message = next_random_hex(&mut seed, 16);
+            for star in [false, true] {
+                let sr = SmallScaleAES::new(rounds, 4, 4, 8, star);
+                let reduced = ReducedRoundAES::new(&KeyNk4::new(&key), rounds, !star);
+                assert_eq!(
+                    sr.encrypt_block(&message, &key),
+                    reduced.encrypt_block(&message)
+                );
+            }
+        }
+
+        for rows in [1, 2, 4] {
+            for columns in 1..=4 {
+                for word_size in [4, 8] {
+                    for star in [false, true] {
+                        let sr = SmallScaleAES::new(4, rows, columns, word_size, star);
+                        let length = (rows * columns * word_size).div_ceil(8);
+                        let mut key = next_random_hex(&mut seed, length);
+                        let mut message = next_random_hex(&mut seed, length);
+                        key.truncate(rows * columns * word_size / 4);
+                        message.truncate(rows * columns * word_size / 4);
+
+                        let ciphertext = sr.encrypt_block(&message, &key);
+                        assert_eq!(ciphertext.len(), message.len());
+                        assert_eq!(sr.decrypt_block(&ciphertext, &key), message);
+                    }
+                }
+            }
+        }
+    }
+    #[test]
+    #[should_panic(expected = "Expected 1, 2 or 4 rows but it was 3")]
+    fn it_rejects_three_rows() {
+        SmallScaleAES::new(1, 3, 1, 4, false);
+    }
+    // Brian Gladman's Rijndael vectors: the plaintext and key are the leading
+    // words of the fractional parts of pi and e.
+    const RIJNDAEL_PLAINTEXT: &str =
+        "3243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c8";
+    const RIJNDAEL_KEY: &str = "2b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfe";
+
+    fn check_rijndael<T>(key: &T, expected: [&str; 5])
+    where
+        T: AESKey,
+    {
+        for (block_words, ciphertext) in (4..=8).zip(expected) {
+            let cipher = Rijndael::new(key, block_words);
+            let plaintext = &RIJNDAEL_PLAINTEXT[..8 * block_words];
+            assert_eq!(cipher.encrypt_block(plaintext), ciphertext);
+            assert_eq!(cipher.decrypt_block(ciphertext), plaintext);
+        }
+    }
+    #[test]
+    fn it_encrypts_rijndael_block_sizes() {
+        assert_eq!(rijndael_num_rounds(4, 4), 10);
+        assert_eq!(rijndael_num_rounds(4, 6), 12);
+        assert_eq!(rijndael_num_rounds(6, 8), 14);
+
+        check_rijndael(
+            &KeyNk4::new(&RIJNDAEL_KEY[..32]),
+            [
+                "3925841d02dc09fbdc118597196a0b32",
+                "16e73aec921314c29df905432bc8968ab64b1f51",
+                "b24d275489e82bb8f7375e0d5fcdb1f481757c538b65148a",
+                "b0a8f78f6b3c66213f792ffd2a61631f79331407a5e5c8d3793aceb1",
+                "7d15479076b69a46ffb3b3beae97ad8313f622f67fedb487de9f06b9ed9c8f19",
+            ],
+        );
+        check_rijndael(
+            &KeyNk5::new(&RIJNDAEL_KEY[..40]),
+            [
+                "231d844639b31b412211cfe93712b880",
+                "0553eb691670dd8a5a5b5addf1aa7450f7a0e587",
+                "738dae25620d3d3beff4a037a04290d73eb33521a63ea568",
+                "08b99944edfce33a2acb131183ab0168446b2d15e958480010f545e3",
+                "514f93fb296b5ad16aa7df8b577abcbd484decacccc7fb1f18dc567309ceeffd",
+            ],
+        );
+        check_rijndael(
+            &KeyNk6::new(&RIJNDAEL_KEY[..48]),
+            [
+                "f9fb29aefc384a250340d833b87ebc00",
+                "73cd6f3423036790463aa9e19cfcde894ea16623",
+                "725ae43b5f3161de806a7c93e0bca93c967ec1ae1b71e1cf",
+                "be4c597d8f7efe22a2f7e5b1938e2564d452a5bfe72399c7af1101e2",
+                "5d7101727bb25781bf6715b0e6955282b9610e23a43c2eb062699f0ebf5887b2",
+            ],
+        );
+        check_rijndael(
+            &KeyNk7::new(&RIJNDAEL_KEY[..56]),
+            [
+                "8faa8fe4dee9eb17caa4797502fc9d3f",
+                "601b5dcd1cf4ece954c740445340bf0afdc048df",
+                "bbfc14180afbf6a36382a061843f0b63e769acdc98769130",
+                "ef529598ecbce297811b49bbed2c33bbe1241d6e1a833dbe119569e8",
+                "d56c5a63627432579e1dd308b2c8f157b40a4bfb56fea1377b25d3ed3d6dbf80",
+            ],
+        );
+        check_rijndael(
+            &KeyNk8::new(RIJNDAEL_KEY),
+            [
+                "1a6e6c2c662e7da6501ffb62bc9e93f3",
+                "579e930b36c1529aa3e86628bacfe146942882cf",
+                "0ebacf199e3315c2e34b24fcc7c46ef4388aa475d66c194c",
+                "02fafc200176ed05deb8edb82a3555b0b10d47a388dfd59cab2f6c11",
+                "a49406115dfb30a40418aafa4869b7c6a886ff31602a7dd19c889dc64f7e4e7a",
+            ],
+        );
+    }
+    #[test]
+    fn it_encrypts_message_160_and_224_key() {
+        let optimizations = [
+            AESOptimization::NoOptimization,
+            AESOptimization::MemoryEfficient,
+            AESOptimization::SpeedEfficient,
+            AESOptimization::CompactSpeedEfficient,
+            AESOptimization::Bitsliced,
+            AESOptimization::Hardware,
+            AESOptimization::VectorPermute,
+        ];
+        let plaintext = &RIJNDAEL_PLAINTEXT[..32];
+        for optimization in optimizations {
+            let key = KeyNk5::new(&RIJNDAEL_KEY[..40]);
+            assert_eq!(key.num_rounds(), 11);
+            let result = encrypt_block(plaintext, key, optimization);
+            assert_eq!(result, "231d844639b31b412211cfe93712b880");
+            assert_eq!(decrypt_block(&result, key, optimization), plaintext);
+
+            let key = KeyNk7::new(&RIJNDAEL_KEY[..56]);
+            assert_eq!(key.num_rounds(), 13);
+            let result = encrypt_block(plaintext, key, optimization);
+            assert_eq!(result, "8faa8fe4dee9eb17caa4797502fc9d3f");
+            assert_eq!(decrypt_block(&result, key, optimization), plaintext);
+        }
+    }
+    fn round_keys_hex<T>(key: &T, first_word: usize, words: usize) -> String
+    where
+        T: AESKey,
+    {
+        (first_word..first_word + words)
+            .map(|i| hex::encode(key.get_round_key(i)))
+            .collect()
+    }
+    #[test]
+    fn it_recovers_master_key_from_round_keys() {
+        assert_eq!(
+            recover_master_key("13111d7fe3944a17f307a78b4d2b30c5", 10, 4),
+            "000102030405060708090a0b0c0d0e0f"
+        );
+
+        let mut seed = 0x853c49e6748fea9b;
+        let hex_key = next_random_hex(&mut seed, 16);
+        let mut key = KeyNk4::new(&hex_key);
+        key.key_expansion(false);
+        for round in 0..=10 {
+            let round_key = round_keys_hex(&key, 4 * round, 4);
+            assert_eq!(recover_master_key(&round_key, round, 4), hex_key);
+        }
+
+        let hex_key = next_random_hex(&mut seed, 24);
+        let mut key = KeyNk6::new(&hex_key);
+        key.key_expansion(false);
+        for round in 0..12 {
+            let round_keys = round_keys_hex(&key, 4 * round, 8);
+            assert_eq!(recover_master_key(&round_keys, round, 6), hex_key);
+        }
+
+        let hex_key = next_random_hex(&mut seed, 32);
+        let mut key = KeyNk8::new(&hex_key);
+        key.key_expansion(false);
+        for round in 0..14 {
+            let round_keys = round_keys_hex(&key, 4 * round, 8);
+            assert_eq!(recover_master_key(&round_keys, round, 8), hex_key);
+        }
+        let schedule = recover_key_schedule(&round_keys_hex(&key, 24, 8), 6, 8);
+        assert_eq!(schedule.len(), 15);
+        for (round, round_key) in schedule.iter().enumerate() {
+            assert_eq!(*round_key, round_keys_hex(&key, 4 * round, 4));
+        }
+
+        let hex_key = next_random_hex(&mut seed, 20);
+        let mut key = KeyNk5::new(&hex_key);
+        key.key_expansion(false);
+        let schedule = recover_key_schedule(&round_keys_hex(&key, 40, 5), 10, 5);
+        assert_eq!(schedule.len(), 12);
+        for (round, round_key) in schedule.iter().enumerate() {
+            assert_eq!(*round_key, round_keys_hex(&key, 4 * round, 4));
+        }
+    }
+    #[test]
+    #[should_panic(
+        expected = "Expected a round of at most 10 for 10 rounds and 4 key words but it was 11"
+    )]
+    fn it_rejects_round_keys_past_the_last_round() {
+        recover_master_key("13111d7fe3944a17f307a78b4d2b30c5", 11, 4);
+    }
+    #[test]
+    fn it_analyses_aes_sbox() {
+        let sbox = aes_sbox();
+        let properties = analyse_sbox(&sbox);
+        assert!(properties.is_permutation);
+        assert_eq!(properties.differential_uniformity, 4);
+        assert_eq!(properties.nonlinearity, 112);
+        assert_eq!(properties.algebraic_degree, 7);
+        assert!(properties.fixed_points.is_empty());
+        assert!(properties.opposite_fixed_points.is_empty());
+
+        let ddt = difference_distribution_table(&sbox);
+        assert_eq!(ddt[0][0], 256);
+        for row in ddt.iter() {
+            assert_eq!(row.iter().map(|entry| *entry as u32).sum::<u32>(), 256);
+        }
+        let lat = linear_approximation_table(&sbox);
+        assert_eq!(lat[0][0], 128);
+        assert_eq!(lat[1][0], 0);
+
+        let csv = table_to_csv(&ddt);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 257);
+        assert!(lines[0].starts_with("in\\out,00,01,02"));
+        assert!(lines[1].starts_with("00,256,0,0"));
+
+        let mut identity = [0u8; 256];
+        for (x, value) in identity.iter_mut().enumerate() {
+            *value = x as u8;
+        }
+        let properties = analyse_sbox(&identity);
+        assert_eq!(properties.differential_uniformity, 256);
+        assert_eq!(properties.nonlinearity, 0);
+        assert_eq!(properties.algebraic_degree, 1);
+        assert_eq!(properties.fixed_points.len(), 256);
+    }
+    #[test]
+    fn it_checks_field_polynomials() {
+        assert!(is_irreducible(0x11b));
+        assert!(is_irreducible(0x11d));
+        assert!(!is_irreducible(0x101));
+        assert_eq!((0x100..0x200).filter(|p| is_irreducible(*p)).count(), 30);
+        assert_eq!(FieldParams::aes().inverse(0x53), 0xca);
+    }
+    #[test]
+    #[should_panic(expected = "0x101 is not an irreducible polynomial")]
+    fn it_rejects_reducible_polynomial() {
+        FieldParams::new(0x101);
+    }
+    #[test]
+    fn it_encrypts_with_sbox_spec() {
+        let spec = SBoxSpec::aes();
+        assert_eq!(spec.sbox_table(), SBOX_TABLE);
+        assert_eq!(spec.inverse_sbox_table(), INVERSE_SBOX_TABLE);
+        assert_eq!(spec.inverse_affine(), (0x4a, 0x05));
+
+        let optimizations = [
+            AESOptimization::NoOptimization,
+            AESOptimization::MemoryEfficient,
+            AESOptimization::SpeedEfficient,
+        ];
+        for optimization in optimizations {
+            let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+            let result = encrypt_block_with_spec(
+                "00112233445566778899aabbccddeeff",
+                key,
+                &spec,
+                optimization,
+            );
+            assert_eq!(result, "69c4e0d86a7b0430d8cdb78070b4c55a");
+            let key =
+                KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+            let result = decrypt_block_with_spec(
+                "8ea2b7ca516745bfeafc49904b496089",
+                key,
+                &spec,
+                optimization,
+            );
+            assert_eq!(result, "00112233445566778899aabbccddeeff");
+        }
+
+        // With a 128-bit key the last round constants depend on the polynomial.
+        let spec = SBoxSpec::new(FieldParams::new(0x11d), 0x1f, 0x63);
+        assert_eq!(spec.rcon(9), 0x1d);
+        for key in [
+            "000102030405060708090a0b0c0d0e0f",
+            "2b7e151628aed2a6abf7158809cf4f3c",
+        ] {
+            let key = KeyNk4::new(key);
+            let expected = encrypt_block_with_spec(
+                "00112233445566778899aabbccddeeff",
+                key,
+                &spec,
+                AESOptimization::NoOptimization,
+            );
+            assert_ne!(expected, "69c4e0d86a7b0430d8cdb78070b4c55a");
+            for optimization in optimizations {
+                assert_eq!(
+                    encrypt_block_with_spec(
+                        "00112233445566778899aabbccddeeff",
+                        key,
+                        &spec,
+                        optimization
+                    ),
+                    expected
+                );
+                assert_eq!(
+                    decrypt_block_with_spec(&expected, key, &spec, optimization),
+                    "00112233445566778899aabbccddeeff"
+                );
+            }
+        }
+        let key = KeyNk6::new("000102030405060708090a0b0c0d0e0f1011121314151617");
+        let expected = encrypt_block_with_spec(
+            "00112233445566778899aabbccddeeff",
+            key,
+            &spec,
+            AESOptimization::NoOptimization,
+        );
+        for optimization in optimizations {
+            assert_eq!(
+                decrypt_block_with_spec(&expected, key, &spec, optimization),
+                "00112233445566778899aabbccddeeff"
+            );
+        }
+
+        let spec = SBoxSpec::new(FieldParams::aes(), 0x07, 0x00);
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        for optimization in optimizations {
+            let result = encrypt_block_with_spec(
+                "00112233445566778899aabbccddeeff",
+                key,
+                &spec,
+                optimization,
+            );
+            assert_eq!(
+                decrypt_block_with_spec(&result, key, &spec, AESOptimization::SpeedEfficient),
+                "00112233445566778899aabbccddeeff"
+            );
+        }
+    }
+    #[test]
+    #[should_panic(
+        expected = "Expected NoOptimization, MemoryEfficient or SpeedEfficient for a custom S-box but it was Bitsliced"
+    )]
+    fn it_rejects_other_optimizations_with_sbox_spec() {
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        encrypt_block_with_spec(
+            "00112233445566778899aabbccddeeff",
+            key,
+            &SBoxSpec::aes(),
+            AESOptimization::Bitsliced,
+        );
+    }
+    #[test]
+    fn it_computes_in_gf256() {
+        let a = Gf256::new(0x57);
+        let b = Gf256::new(0x83);
+        assert_eq!(a + b, Gf256::new(0xd4));
+        assert_eq!(a - b, a + b);
+        assert_eq!(a * b, Gf256::new(0xc1));
+        assert_eq!(a * Gf256::new(0x13), Gf256::new(0xfe));
+        assert_eq!(Gf256::new(0x53).inv(), Gf256::new(0xca));
+        assert_eq!(Gf256::ZERO.inv(), Gf256::ZERO);
+        assert_eq!(Gf256::new(0x03).pow(255), Gf256::ONE);
+
+        for x in 0..=255u8 {
+            let x = Gf256::from(x);
+            assert_eq!(x.xtime(), x * Gf256::new(0x02));
+            assert_eq!(u8::from(x * Gf256::new(0x1d)), mult(x.value(), 0x1d));
+            assert_eq!(mult(x.value(), 0x1d), mult_with(x.value(), 0x1d, 0x11b, 8));
+            if x != Gf256::ZERO {
+                assert_eq!(x * x.inv(), Gf256::ONE);
+                assert_eq!((a * x) / x, a);
+            }
+        }
+    }
+    #[test]
+    #[should_panic(expected = "0x3 has no inverse modulo 0x101, the modulus is not irreducible")]
+    fn it_rejects_inverse_in_reducible_ring() {
+        inverse_with(0x03, 0x101, 8);
+    }
+    #[test]
+    #[should_panic(expected = "Division by zero in GF(2^8)")]
+    fn it_rejects_division_by_zero() {
+        let _ = Gf256::ONE / Gf256::ZERO;
+    }
+    #[test]
+    fn it_simulates_power_traces() {
+        let key = KeyNk4::new("2b7e151628aed2a6abf7158809cf4f3c");
+        let key_bytes = decode_to_hex_vector("2b7e151628aed2a6abf7158809cf4f3c");
+
+        let simulator = PowerTraceSimulator::new(LeakageModel::HammingWeight, 0.0);
+        let set = simulator.simulate(key, 20);
+        assert_eq!(set.traces.len(), 20);
+        for (plaintext, trace) in set.plaintexts.iter().zip(set.traces.iter()) {
+            assert_eq!(trace.len(), simulator.samples_per_trace());
+            for i in 0..16 {
+                let added = plaintext[i] ^ key_bytes[i];
+                assert_eq!(trace[i], added.count_ones() as f64);
+                assert_eq!(trace[16 + i], sub_byte(added).count_ones() as f64);
+            }
+        }
+
+        let simulator = PowerTraceSimulator::new(LeakageModel::HammingDistance, 0.0);
+        let set = simulator.simulate(key, 5);
+        for (plaintext, trace) in set.plaintexts.iter().zip(set.traces.iter()) {
+            for i in 0..16 {
+                let added = plaintext[i] ^ key_bytes[i];
+                assert_eq!(trace[i], key_bytes[i].count_ones() as f64);
+                assert_eq!(trace[16 + i], (added ^ sub_byte(added)).count_ones() as f64);
+            }
+        }
+
+        let mut simulator = PowerTraceSimulator::new(LeakageModel::HammingWeight, 2.0);
+        simulator.rounds = 2;
+        let noisy = simulator.simulate(key, 200);
+        assert_eq!(noisy.traces[0].len(), 64);
+        assert_eq!(noisy.traces, simulator.simulate(key, 200).traces);
+        let clean = PowerTraceSimulator {
+            noise: 0.0,
+            ..simulator
+        }
+        .simulate(key, 200);
+        let residuals: Vec<f64> = noisy
+            .traces
+            .iter()
+            .flatten()
+            .zip(clean.traces.iter().flatten())
+            .map(|(n, c)| n - c)
+            .collect();
+        let mean = residuals.iter().sum::<f64>() / residuals.len() as f64;
+        let variance =
+            residuals.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / residuals.len() as f64;
+        assert!(mean.abs() < 0.1);
+        assert!((variance.sqrt() - 2.0).abs() < 0.1);
+
+        let npy = traces_to_npy(&clean.traces);
+        let header_length = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!((10 + header_length) % 64, 0);
+        let header = std::str::from_utf8(&npy[10..10 + header_length]).unwrap();
+        assert!(
+            header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (200, 64), }")
+        );
+        assert!(header.ends_with('\n'));
+        assert_eq!(npy.len(), 10 + header_length + 200 * 64 * 8);
+        assert_eq!(
+            f64::from_le_bytes(
+                npy[10 + header_length..18 + header_length]
+                    .try_into()
+                    .unwrap()
+            ),
+            clean.traces[0][0]
+        );
+        let npy = plaintexts_to_npy(&clean.plaintexts);
+        assert_eq!(npy[npy.len() - 16..], clean.plaintexts[199]);
+
+        let path = std::env::temp_dir().join("aes_simulated_traces.npy");
+        write_traces_npy(&path, &clean.traces).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), traces_to_npy(&clean.traces));
+        std::fs::remove_file(path).unwrap();
+    }
+    #[test]
+    fn it_recovers_key_with_cpa() {
+        assert!((pearson_correlation(&[1.0, 2.0, 3.0], &[2.0, 4.0, 6.0]) - 1.0).abs() < 1e-12);
+        assert!((pearson_correlation(&[1.0, 2.0, 3.0], &[3.0, 2.0, 1.0]) + 1.0).abs() < 1e-12);
+
+        let key = KeyNk4::new("2b7e151628aed2a6abf7158809cf4f3c");
+        let correct_key: [u8; 16] = decode_to_hex_vector("2b7e151628aed2a6abf7158809cf4f3c")
+            .try_into()
+            .unwrap();
+
+        for model in [LeakageModel::HammingWeight, LeakageModel::HammingDistance] {
+            let simulator = PowerTraceSimulator::new(model, 1.0);
+            let result = cpa_attack_set(&simulator.simulate(key, 150), model);
+            assert_eq!(result.best_key(), correct_key);
+        }
+
+        let simulator = PowerTraceSimulator::new(LeakageModel::HammingWeight, 2.0);
+        let evaluation = evaluate_cpa(&simulator, key, correct_key, &[5, 300], 3);
+        assert_eq!(evaluation[0].traces, 5);
+        assert!(evaluation[0].guessing_entropy > 10.0);
+        assert_eq!(evaluation[0].success_rate, 0.0);
+        assert_eq!(evaluation[1].guessing_entropy, 1.0);
+        assert_eq!(evaluation[1].success_rate, 1.0);
+    }
+    #[test]
+    fn it_recovers_aes_128_key_with_differential_fault_analysis() {
+        let hex_key = "000102030405060708090a0b0c0d0e0f";
+        let key = KeyNk4::new(hex_key);
+        let mut seed = 0x2545f4914f6cdd1d;
+        let mut pairs = Vec::new();
+        for column in 0..4 {
+            for fault in [0x1e, 0xa7] {
+                let message = next_random_hex(&mut seed, 16);
+                let correct = encrypt_block(&message, key, AESOptimization::NoOptimization);
+                let mut injector = FaultInjector::new(vec![(4 * column + column, fault)]);
+                let faulty = encrypt_block_observed(
+                    &message,
+                    key,
+                    AESOptimization::NoOptimization,
+                    &mut injector,
+                );
+                let correct: [u8; 16] = hex::decode(correct).unwrap().try_into().unwrap();
+                let faulty: [u8; 16] = hex::decode(faulty).unwrap().try_into().unwrap();
+                assert_eq!(fault_column(&correct, &faulty), Some(column));
+                pairs.push((correct, faulty));
+            }
+        }
+
+        let one_per_column: Vec<([u8; 16], [u8; 16])> = pairs.iter().step_by(2).copied().collect();
+        let result = recover_last_round_key(&one_per_column);
+        assert!(result.key.is_none());
+        assert!(result.candidates.iter().all(|column| !column.is_empty()));
+
+        let result = recover_last_round_key(&pairs);
+        let last_round_key = hex::encode(result.key.unwrap());
+        assert_eq!(last_round_key, "13111d7fe3944a17f307a78b4d2b30c5");
+        assert_eq!(recover_master_key(&last_round_key, 10, 4), hex_key);
+    }
+    #[test]
+    fn it_injects_faults_into_reference_decryption() {
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let mut injector = FaultInjector {
+            round: 10,
+            step: RoundStep::AddRoundKey,
+            faults: vec![(3, 0x5a)],
+        };
+        let result = decrypt_block_observed(
+            "69c4e0d86a7b0430d8cdb78070b4c55a",
+            key,
+            AESOptimization::NoOptimization,
+            &mut injector,
+        );
+        // 0x33 ^ 0x5a at index 3 of the plaintext.
+        assert_eq!(result, "00112269445566778899aabbccddeeff");
+    }
+    #[test]
+    #[should_panic(expected = "Expected NoOptimization for fault injection but it was Hardware")]
+    fn it_rejects_fault_injection_without_reference_rounds() {
+        let key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        let mut injector = FaultInjector::new(vec![(0, 0x1e)]);
+        encrypt_block_observed(
+            "00112233445566778899aabbccddeeff",
+            key,
+            AESOptimization::Hardware,
+            &mut injector,
+        );
+    }
+    #[test]
+    fn it_recovers_4_round_key_with_square_attack() {
+        let mut seed = 0x9e3779b97f4a7c15;
+        let hex_key = next_random_hex(&mut seed, 16);
+        let mut key = KeyNk4::new(&hex_key);
+        key.key_expansion(false);
+        let last_round_key = round_keys_hex(&key, 16, 4);
+        let cipher = ReducedRoundAES::new(&key, 4, false);
+
+        // Three rounds keep every byte of a Lambda-set balanced.
+        let base: [u8; 16] = hex::decode(next_random_hex(&mut seed, 16))
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let three_rounds = ReducedRoundAES::new(&key, 3, true);
+        let sums = encrypt_lambda_set(&three_rounds, &lambda_set(base, 5))
+            .iter()
+            .fold([0u8; 16], |mut sum, state| {
+                sum.iter_mut().zip(state.iter()).for_each(|(s, b)| *s ^= b);
+                sum
+            });
+        assert_eq!(sums, [0u8; 16]);
+
+        let ciphertexts = encrypt_lambda_set(&cipher, &lambda_set(base, 0));
+        let correct = hex::decode(&last_round_key).unwrap();
+        for (index, byte) in correct.iter().enumerate() {
+            assert!(balanced_key_bytes(&ciphertexts, index).contains(byte));
+        }
+
+        let result = square_attack(&cipher, &[base]);
+        assert!(result
+            .candidates
+            .iter()
+            .zip(correct.iter())
+            .all(|(candidates, byte)| candidates.contains(byte)));
+
+        let bases: Vec<[u8; 16]> = (0..3)
+            .map(|_| {
+                hex::decode(next_random_hex(&mut seed, 16))
+                    .unwrap()
+                    .try_into()
+                    .unwrap()
+            })
+            .collect();
+        let result = square_attack(&cipher, &bases);
+        assert_eq!(result.remaining_keys(), 1);
+        let recovered = hex::encode(result.key.unwrap());
+        assert_eq!(recovered, last_round_key);
+        assert_eq!(recover_master_key(&recovered, 4, 4), hex_key);
+    }
+    #[test]
+    #[should_panic(expected = "Expected no MixColumns in the last round but it had one")]
+    fn it_rejects_square_attack_with_final_mix_columns() {
+        let mut key = KeyNk4::new("000102030405060708090a0b0c0d0e0f");
+        key.key_expansion(false);
+        let cipher = ReducedRoundAES::new(&key, 4, true);
+        square_attack(&cipher, &[[0u8; 16]]);
+    }
+    #[test]
+    fn it_hides_first_order_leakage_with_masking() {
+        let hex_key = "2b7e151628aed2a6abf7158809cf4f3c";
+        let correct_key: [u8; 16] = decode_to_hex_vector(hex_key).try_into().unwrap();
+        let table = masked_sbox_table(0x3c, 0xa5);
+        assert!((0..=255u8).all(|v| table[(v ^ 0x3c) as usize] == sub_byte(v) ^ 0xa5));
+
+        let mut rng = SimulationRng::new(7);
+        let mut seed = 0x1234567890abcdef;
+        let block: [u8; 16] = hex::decode(next_random_hex(&mut seed, 16))
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let masked = MaskedState::mask(State::from_hex_vector(&block), &mut rng);
+        assert_ne!(*masked.shares[0], block);
+        assert_eq!(*masked.unmask(), block);
+
+        let message = next_random_hex(&mut seed, 16);
+        let key_192 = KeyNk6::new(&next_random_hex(&mut seed, 24));
+        assert_eq!(
+            MaskedAES::new(&key_192).encrypt_block(&message, &mut rng),
+            encrypt_block(&message, key_192, AESOptimization::NoOptimization)
+        );
+        let key_256 = KeyNk8::new(&next_random_hex(&mut seed, 32));
+        assert_eq!(
+            MaskedAES::new(&key_256).encrypt_block(&message, &mut rng),
+            encrypt_block(&message, key_256, AESOptimization::NoOptimization)
+        );
+
+        let key = KeyNk4::new(hex_key);
+        let cipher = MaskedAES::new(&key);
+        for _ in 0..10 {
+            let message = next_random_hex(&mut seed, 16);
+            assert_eq!(
+                cipher.encrypt_block(&message, &mut rng),
+                encrypt_block(&message, key, AESOptimization::NoOptimization)
+            );
+        }
+
+        for model in [LeakageModel::HammingWeight, LeakageModel::HammingDistance] {
+            let mut simulator = PowerTraceSimulator::new(model, 0.0);
+            let unmasked = cpa_attack_set(&simulator.simulate(key, 150), model);
+            assert_eq!(unmasked.best_key(), correct_key);
+
+            simulator.masked = true;
+            let masked = cpa_attack_set(&simulator.simulate(key, 300), model);
+            let found = (0..16)
+                .filter(|byte| masked.rank_of(*byte, correct_key[*byte]) == 1)
+                .count();
+            assert!(found <= 2);
+            assert!((0..16).all(|byte| masked.scores[byte][correct_key[byte] as usize] < 0.3));
+        }
+    }
+    #[test]
+    fn it_exports_algebraic_systems() {
+        assert_eq!(
+            sbox_relations(&SmallScaleAES::new(1, 1, 1, 4, false)).len(),
+            21
+        );
+        assert_eq!(
+            sbox_relations(&SmallScaleAES::new(1, 1, 1, 8, false)).len(),
+            39
+        );
+
+        let mut seed = 0x5851f42d4c957f2d;
+        let cipher = SmallScaleAES::new(2, 2, 2, 4, false);
+        let key = cipher.words_from_hex(&next_random_hex(&mut seed, 2));
+        let wrong_key: Vec<u8> = key.iter().map(|word| word ^ 0x1).collect();
+        let plaintext = cipher.words_from_hex(&next_random_hex(&mut seed, 2));
+        let ciphertext = cipher.encrypt(&plaintext, &key);
+        let model = AlgebraicModel::new(cipher, &[(plaintext, ciphertext)]);
+        let system = model.gf2_system();
+        let cnf = system.to_cnf();
+
+        let values = model.assignment(&key);
+        assert!(model.is_satisfied_by(&values));
+        let bits = model.bit_assignment(&values);
+        assert!(system.is_satisfied_by(&bits));
+        assert!(cnf.is_satisfied_by(&cnf.complete_assignment(&bits)));
+
+        let values = model.assignment(&wrong_key);
+        assert!(!model.is_satisfied_by(&values));
+        let bits = model.bit_assignment(&values);
+        assert!(!system.is_satisfied_by(&bits));
+        assert!(!cnf.is_satisfied_by(&cnf.complete_assignment(&bits)));
+
+        let dimacs = cnf.to_dimacs();
+        assert!(dimacs.starts_with("c 1 k0_0_0\n"));
+        assert!(dimacs.contains(&format!(
+            "p cnf {} {}\n",
+            cnf.num_variables(),
+            cnf.clauses.len()
+        )));
+        assert!(system.to_anf_string().lines().count() == system.equations.len() + 1);
+
+        let hex_key = next_random_hex(&mut seed, 16);
+        let aes_key = KeyNk4::new(&hex_key);
+        let reduced = ReducedRoundAES::new(&aes_key, 2, false);
+        let pairs: Vec<([u8; 16], [u8; 16])> = (0..2)
+            .map(|_| {
+                let plaintext = next_random_hex(&mut seed, 16);
+                let ciphertext = reduced.encrypt_block(&plaintext);
+                (
+                    hex::decode(plaintext).unwrap().try_into().unwrap(),
+                    hex::decode(ciphertext).unwrap().try_into().unwrap(),
+                )
+            })
+            .collect();
+        let model = AlgebraicModel::reduced_round_aes(2, false, &pairs);
+        let values = model.assignment(&hex::decode(&hex_key).unwrap());
+        assert!(model.is_satisfied_by(&values));
+        assert!(model
+            .gf2_system()
+            .is_satisfied_by(&model.bit_assignment(&values)));
+
+        // The affine map of the AES S-box as a linearized polynomial.
+        let system = model.to_gf2e_system();
+        assert!(system.contains(
+            "y0_1_0 + 0x5*w0_1_0 + 0x9*w0_1_0^2 + 0xf9*w0_1_0^4 + 0x25*w0_1_0^8 + \
+             0xf4*w0_1_0^16 + w0_1_0^32 + 0xb5*w0_1_0^64 + 0x8f*w0_1_0^128 + 0x63\n"
+        ));
+        assert!(system.contains("x0_1_0^2*w0_1_0 + x0_1_0\n"));
+        assert!(system.contains("k1_0 + k0_0 + s1_0 + 0x1\n"));
+    }
+    #[test]
+    fn it_finds_key_schedules_in_memory() {
+        let mut seed = 0xda942042e4dd58b5;
+        let mut memory = hex::decode(next_random_hex(&mut seed, 1200)).unwrap();
+
+        let key_128 = next_random_hex(&mut seed, 16);
+        let mut key = KeyNk4::new(&key_128);
+        key.key_expansion(false);
+        let schedule_128 = hex::decode(round_keys_hex(&key, 0, 44)).unwrap();
+        memory[37..37 + 176].copy_from_slice(&schedule_128);
+
+        let key_256 = next_random_hex(&mut seed, 32);
+        let mut key = KeyNk8::new(&key_256);
+        key.key_expansion(false);
+        let schedule_256 = hex::decode(round_keys_hex(&key, 0, 60)).unwrap();
+        memory[400..400 + 240].copy_from_slice(&schedule_256);
+
+        // Decayed bits, two of them in the key itself.
+        let key_192 = next_random_hex(&mut seed, 24);
+        let mut key = KeyNk6::new(&key_192);
+        key.key_expansion(false);
+        let schedule_192 = hex::decode(round_keys_hex(&key, 0, 52)).unwrap();
+        memory[800..800 + 208].copy_from_slice(&schedule_192);
+        memory[800 + 3] ^= 0x10;
+        memory[800 + 17] ^= 0x01;
+        memory[800 + 150] ^= 0x80;
+
+        let found = KeyScanner::new(0).scan(&memory);
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].offset, found[0].key_length), (37, 4));
+        assert_eq!(found[0].key, key_128);
+        assert_eq!((found[1].offset, found[1].key_length), (400, 8));
+        assert_eq!(found[1].key, key_256);
+        assert_eq!(found[1].bit_errors, 0);
+
+        let found = KeyScanner::new(4).scan(&memory);
+        assert_eq!(found.len(), 3);
+        assert_eq!((found[2].offset, found[2].key_length), (800, 6));
+        assert_eq!(found[2].key, key_192);
+        assert_eq!(found[2].bit_errors, 3);
+
+        let scanner = KeyScanner {
+            key_lengths: vec![6],
+            max_bit_errors: 4,
+        };
+        let path = std::env::temp_dir().join("aes_memory_image.bin");
+        std::fs::write(&path, &memory).unwrap();
+        let found = scanner.scan_file(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].key, key_192);
+
+        // Chunks shorter than a schedule, every schedule crosses a chunk boundary.
+        let chunked = KeyScanner::new(4)
+            .scan_reader(std::io::Cursor::new(&memory), 100)
+            .unwrap();
+        assert_eq!(chunked, KeyScanner::new(4).scan(&memory));
+    }
+    #[test]
+    fn it_propagates_key_differences() {
+        let mut difference = [0u8; 16];
+        difference[0] = 0x01;
+        let schedule = propagate_key_difference(&difference);
+        let fixed = [
+            ByteDifference::Fixed(0x01),
+            ByteDifference::Zero,
+            ByteDifference::Zero,
+            ByteDifference::Zero,
+        ];
+        assert_eq!(schedule.round_key(1), [fixed; 4]);
+        // RotWord moves byte 0 of w[7] into the S-box of byte 3 of w[8].
+        assert_eq!(
+            schedule.words[8],
+            [
+                ByteDifference::Fixed(0x01),
+                ByteDifference::Zero,
+                ByteDifference::Zero,
+                ByteDifference::Active
+            ]
+        );
+        assert_eq!(schedule.sboxes.len(), 40);
+        assert_eq!(
+            schedule.affected_round_keys(),
+            (0..=10).collect::<Vec<usize>>()
+        );
+        assert!(schedule.report().starts_with(
+            "round[ 0] F... .... .... ....  0 active, 0 possibly active S-boxes\n\
+             round[ 1] F... F... F... F...  0 active, 0 possibly active S-boxes\n\
+             round[ 2] F..A ...A F..A ...A  1 active, 0 possibly active S-boxes\n"
+        ));
+
+        let zero = propagate_key_difference(&[0u8; 32]);
+        assert_eq!(zero.active_sboxes() + zero.possibly_active_sboxes(), 0);
+        assert!(zero.affected_round_keys().is_empty());
+
+        // A difference in w[4] of an AES-256 key reaches w[12] through the linear part
+        // only, the first active S-box computes w[16] from w[15].
+        let mut difference = [0u8; 32];
+        difference[16] = 0x80;
+        let schedule = propagate_key_difference(&difference);
+        assert_eq!(schedule.sboxes.len(), 52);
+        assert_eq!(schedule.words[12][0], ByteDifference::Fixed(0x80));
+        assert_eq!(
+            schedule
+                .sboxes
+                .iter()
+                .find(|(_, input)| input.is_non_zero())
+                .map(|(word, _)| *word),
+            Some(16)
+        );
+        assert_eq!(schedule.affected_round_keys()[0], 1);
+
+        let mut seed = 0x7a1b9c2d3e4f5061;
+        for bytes in [16, 24, 32] {
+            let difference = hex::decode(next_random_hex(&mut seed, 1)).unwrap()[0];
+            let mut key_difference = vec![0u8; bytes];
+            key_difference[bytes - 1] = difference;
+            key_difference[1] = difference;
+            let schedule = propagate_key_difference(&key_difference);
+            for _ in 0..5 {
+                let key = hex::decode(next_random_hex(&mut seed, bytes)).unwrap();
+                let pair = key_pair_difference(&key, &key_difference);
+                assert!(schedule
+                    .words
+                    .iter()
+                    .flatten()
+                    .zip(pair.words.iter().flatten())
+                    .all(|(symbolic, actual)| symbolic.allows(*actual)));
+                assert!(pair.active_sboxes() >= schedule.active_sboxes());
+                assert!(
+                    pair.active_sboxes()
+                        <= schedule.active_sboxes() + schedule.possibly_active_sboxes()
+                );
+                assert!(pair
+                    .sboxes
+                    .iter()
+                    .all(|sbox| (sbox.input == 0) == (sbox.output == 0)));
+
+                let mut other_key: Vec<u8> = key.clone();
+                other_key
+                    .iter_mut()
+                    .zip(key_difference.iter())
+                    .for_each(|(k, d)| *k ^= d);
+                let words = bytes / 4;
+                let (first, second) = if words == 4 {
+                    let (mut a, mut b) = (
+                        KeyNk4::new(&hex::encode(&key)),
+                        KeyNk4::new(&hex::encode(&other_key)),
+                    );
+                    a.key_expansion(false);
+                    b.key_expansion(false);
+                    (round_keys_hex(&a, 0, 44), round_keys_hex(&b, 0, 44))
+                } else if words == 6 {
+                    let (mut a, mut b) = (
+                        KeyNk6::new(&hex::encode(&key)),
+                        KeyNk6::new(&hex::encode(&other_key)),
+                    );
+                    a.key_expansion(false);
+                    b.key_expansion(false);
+                    (round_keys_hex(&a, 0, 52), round_keys_hex(&b, 0, 52))
+                } else {
+                    let (mut a, mut b) = (
+                        KeyNk8::new(&hex::encode(&key)),
+                        KeyNk8::new(&hex::encode(&other_key)),
+                    );
+                    a.key_expansion(false);
+                    b.key_expansion(false);
+                    (round_keys_hex(&a, 0, 60), round_keys_hex(&b, 0, 60))
+                };
+                let expected: Vec<u8> = hex::decode(first)
+                    .unwrap()
+                    .iter()
+                    .zip(hex::decode(second).unwrap().iter())
+                    .map(|(a, b)| a ^ b)
+                    .collect();
+                assert_eq!(pair.words.concat(), expected);
+            }
+        }
+    }
+    #[test]
+    fn it_counts_active_sboxes_of_truncated_trails() {
+        let matrix = mix_columns_matrix();
+        assert_eq!(matrix[0], [2, 3, 1, 1]);
+        assert_eq!(matrix[3], [3, 1, 1, 2]);
+        assert!(is_mds(&matrix));
+        assert!(!is_mds(&[
+            [1, 1, 0, 0],
+            [0, 1, 1, 0],
+            [0, 0, 1, 1],
+            [1, 0, 0, 1]
+        ]));
+        assert_eq!(branch_number(&matrix), 5);
+        assert_eq!(
+            branch_number(&[[1, 1, 0, 0], [0, 1, 1, 0], [0, 0, 1, 1], [1, 0, 0, 1]]),
+            3
+        );
+
+        // ShiftRows moves row r of column c to column c - r.
+        assert_eq!(shift_rows_pattern(0b0010), 0b0010 << 12);
+        assert_eq!(shift_rows_pattern(0x8421), 0x000f);
+        assert_eq!(mix_columns_transitions(0x0001), vec![0x000f]);
+        assert_eq!(mix_columns_transitions(0x0003).len(), 5);
+        assert_eq!(mix_columns_transitions(0x0033).len(), 5 * 5);
+        assert_eq!(pattern_to_string(0x8421), "x...\n.x..\n..x.\n...x\n");
+
+        let trails: Vec<_> = (1..=5).map(min_active_sboxes).collect();
+        let bounds: Vec<u32> = trails.iter().map(|trail| trail.active_sboxes).collect();
+        assert_eq!(bounds, vec![1, 5, 9, 25, 26]);
+
+        let trail = &trails[3];
+        assert_eq!(trail.patterns.len(), 4);
+        assert_eq!(
+            trail
+                .patterns
+                .iter()
+                .map(|pattern| pattern.count_ones())
+                .sum::<u32>(),
+            25
+        );
+        for pair in trail.patterns.windows(2) {
+            assert!(mix_columns_transitions(shift_rows_pattern(pair[0])).contains(&pair[1]));
+        }
+    }
+    #[test]
+    fn it_generates_ctr_drbg_cavp_vectors() {
+        let bytes = |data: &str| decode_to_hex_vector(data);
+        let generate = |drbg: &mut CtrDrbg, length: usize, additional_input: &str| {
+            let mut output = vec![0u8; length];
+            drbg.generate(&mut output, &bytes(additional_input))
+                .unwrap_or_else(|error| panic!("Generate failed: {:?}", error));
+            hex::encode(output)
+        };
+
+        // AES-128 with derivation function, no reseed (CAVS 14.3).
+        let mut drbg = CtrDrbg::new(
+            16,
+            true,
+            &bytes("b408cefb5bc7157d3f26cb95a8b1d7ac"),
+            &bytes("026c768fd577b92a"),
+            &[],
+        )
+        .unwrap();
+        generate(&mut drbg, 64, "5737ef81dee365b6dadb3feebf5d1084");
+        assert_eq!(
+            generate(&mut drbg, 64, "3368a516b3431a3daaa60dc8743c8297"),
+            "4e909ebb24147a0004063a5e47ee044fead610d62324bd0f963f756fb91361e8\
+             b87e3a76a398143fe88130fe1b547b661a6480c711b739f18a9df3ae51d41bc9"
+        );
+
+        // AES-128 and AES-192 without derivation function, no reseed (CAVS 14.3).
+        let mut drbg = CtrDrbg::new(
+            16,
+            false,
+            &bytes("ce50f33da5d4c1d3d4004eb35244b7f2cd7f2e5076fbf6780a7ff634b249a5fc"),
+            &[],
+            &[],
+        )
+        .unwrap();
+        generate(&mut drbg, 64, "");
+        assert_eq!(
+            generate(&mut drbg, 64, ""),
+            "6545c0529d372443b392ceb3ae3a99a30f963eaf313280f1d1a1e87f9db373d3\
+             61e75d18018266499cccd64d9bbb8de0185f213383080faddec46bae1f784e5a"
+        );
+        let mut drbg = CtrDrbg::new(
+            24,
+            false,
+            &bytes(
+                "f1ef7eb311c850e189be229df7e6d68f1795aa8e21d93504e75abe78f0413958\
+                 73540386812a9a2a",
+            ),
+            &[],
+            &[],
+        )
+        .unwrap();
+        drbg.set_optimization(AESOptimization::Hardware);
+        generate(&mut drbg, 64, "");
+        assert_eq!(
+            generate(&mut drbg, 64, ""),
+            "6bb0aa5b4b97ee83765736ad0e9068dfef0ccfc93b71c1d3425302ef7ba4635f\
+             fc09981d262177e208a7ec90a557b6d76112d56c40893892c3034835036d7a69"
+        );
+
+        // AES-256 with derivation function, personalization string and reseed.
+        let entropy = bytes(
+            "a53e371017439193591e475087aaddd5c1c386cdca0ddb68e002d80fdc401a47\
+             dd40e5987b2716731568d276bf0c6715757903d3dede914642ddd467c879c81e",
+        );
+        let nonce = bytes("a94da55afdc50ce51c9a3b8a4c448440");
+        let personalization =
+            bytes("8b52a24a93c34ea71e1ca705eb829ba65de4d4e07fa3d86b37845ff1c7d5f6d2");
+        let mut drbg = CtrDrbg::new(32, true, &entropy[..32], &nonce, &personalization).unwrap();
+        generate(
+            &mut drbg,
+            16,
+            "20f422edf85ca16a01cfbe5f8d6c947fae12a857db2aa9bfc7b36581808d0d46",
+        );
+        drbg.reseed(
+            &entropy[32..],
+            &bytes("7fd81fbd2ab51c115d834e99f65ca54020ed388ed59ee07593fe125e5d73fb75"),
+        )
+        .unwrap();
+        assert_eq!(
+            generate(
+                &mut drbg,
+                16,
+                "cd2cff14693e4c9efdfe260de986004930bab1c65057772a62392c3b74ebc90d"
+            ),
+            "4f78beb94d978ce9d097feadfafd355e"
+        );
+
+        // AES-256 without derivation function.
+        let mut drbg = CtrDrbg::new(
+            32,
+            false,
+            &bytes(
+                "ae7ebe062971f5eb32e5b21444750785de816595ad2cbe80a209c8f8ab04b546\
+                 8166de8c6ae522d8f10b56386a3b424f",
+            ),
+            &[],
+            &bytes(
+                "55860dae57fcac297087c137efb796878a75868f6e7681114e9b73ed0c67e3c6\
+                 2bfc9f5d77e8caa59bcdb223f4ffd247",
+            ),
+        )
+        .unwrap();
+        drbg.reseed(
+            &bytes(
+                "a42407931bfeca70e6ee5dd197021a129525051c07468e8b25587c5ad50abe92\
+                 04e882fe847b8fd47cf7b4360e5aa034",
+            ),
+            &bytes(
+                "ee4c88d1eb05f4853663eada501d2fc4b4984b283a88db579af2113031e03d9b\
+                 c570de943dd168918f3ba8065581fea7",
+            ),
+        )
+        .unwrap();
+        generate(
+            &mut drbg,
+            64,
+            "4b4b03ef19b0f259dca2b3ee3ae4cd86c3895a784b3d8eee043a2003c08289f8\
+             fffdad141e6b1ab2174d8d5d79c1e581",
+        );
+        assert_eq!(
+            generate(
+                &mut drbg,
+                64,
+                "3062b33f116b46e20fe3c354726ae9b2a3a4c51922c8107863cb86f1f0bdad75\
+                 54075659d91c371e2b11b1e8106a1ed5"
+            ),
+            "0d270518baeafac160ff1cb28c11ef68712c764c0c01674e6c9ca2cc9c7e0e8a\
+             ccfd3c753635ee070081eee7628af6187fbc2854b3c204461a796cf3f3fcb092"
+        );
+
+        // Reseed counter limit.
+        let mut drbg = CtrDrbg::new(16, true, &[7; 16], &[1; 8], &[]).unwrap();
+        drbg.reseed_interval = 2;
+        let mut output = [0u8; 16];
+        assert_eq!(drbg.generate(&mut output, &[]), Ok(()));
+        assert_eq!(drbg.generate(&mut output, &[]), Ok(()));
+        assert_eq!(drbg.reseed_counter(), 3);
+        assert_eq!(
+            drbg.generate(&mut output, &[]),
+            Err(CtrDrbgError::ReseedRequired)
+        );
+        assert_eq!(
+            drbg.generate(&mut vec![0; MAX_BYTES_PER_REQUEST + 1], &[]),
+            Err(CtrDrbgError::RequestTooLarge(MAX_BYTES_PER_REQUEST + 1))
+        );
+        drbg.reseed(&[8; 16], &[]).unwrap();
+        assert_eq!(drbg.generate(&mut output, &[]), Ok(()));
+
+        // Bad lengths are errors, the debug output leaves out K and V.
+        assert_eq!(
+            CtrDrbg::new(20, true, &[7; 20], &[], &[]).err(),
+            Some(CtrDrbgError::InvalidKeyLength(20))
+        );
+        assert_eq!(
+            CtrDrbg::new(16, false, &[7; 16], &[], &[]).err(),
+            Some(CtrDrbgError::InvalidEntropyLength(16))
+        );
+        assert_eq!(
+            CtrDrbg::new(16, false, &[7; 32], &[], &[1; 33]).err(),
+            Some(CtrDrbgError::InputTooLong(33))
+        );
+        assert_eq!(
+            drbg.reseed(&[8; 15], &[]),
+            Err(CtrDrbgError::InvalidEntropyLength(15))
+        );
+        let mut drbg = CtrDrbg::new(16, false, &[0x5a; 32], &[], &[]).unwrap();
+        assert_eq!(
+            drbg.generate(&mut output, &[1; 33]),
+            Err(CtrDrbgError::InputTooLong(33))
+        );
+        let debug = format!("{:?}", drbg);
+        assert!(debug.starts_with("CtrDrbg { key_length: 16, derivation_function: false"));
+        assert!(!debug.contains("key:") && !debug.contains("v:"));
+
+        // RngCore draws little-endian words from the same stream.
+        let mut first = CtrDrbg::new(16, true, &[7; 16], &[1; 8], &[]).unwrap();
+        let mut second = first.clone();
+        let mut stream = [0u8; 8];
+        second.fill_bytes(&mut stream);
+        assert_eq!(first.next_u64(), u64::from_le_bytes(stream));
+        second.fill_bytes(&mut stream[..4]);
+        assert_eq!(
+            first.next_u32(),
+            u32::from_le_bytes(stream[..4].try_into().unwrap())
+        );
+        assert_eq!(first.try_fill_bytes(&mut [0; 3]), Ok(()));
+    }
     #[test]
     fn it_expands_key_128_bit_key() {
         let mut key = KeyNk4::new("2b7e151628aed2a6abf7158809cf4f3c");
@@ -488,5 +2798,29 @@ mod tests {
         let key = KeyNk4::new("2b7e151628aed2a6abf7158809cf4f3c");
         let ciphertext = encrypt_ecb(message, key);
         assert_eq!(expected_ciphertext, ciphertext);
+
+        let ciphertext = encrypt_ecb_with_optimization(message, key, AESOptimization::Hardware);
+        assert_eq!(expected_ciphertext, ciphertext);
+
+        let ciphertext =
+            encrypt_ecb_with_optimization(message, key, AESOptimization::VectorPermute);
+        assert_eq!(expected_ciphertext, ciphertext);
+    }
+
+    #[test]
+    fn it_encrypts_and_decrypts_interleaved_blocks_with_hardware() {
+        let key = KeyNk8::new("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let mut blocks: Vec<[u8; 16]> = (0..19u8).map(|i| [i.wrapping_mul(37); 16]).collect();
+        let plaintext = blocks.clone();
+
+        hardware_encrypt_blocks(&mut blocks, &mut key.clone());
+        for (block, message) in blocks.iter().zip(plaintext.iter()) {
+            let expected =
+                encrypt_block(&hex::encode(message), key, AESOptimization::NoOptimization);
+            assert_eq!(hex::encode(block), expected);
+        }
+
+        hardware_decrypt_blocks(&mut blocks, &mut key.clone());
+        assert_eq!(blocks, plaintext);
     }
 }